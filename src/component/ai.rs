@@ -45,6 +45,13 @@ impl AI
         &&self.state
     }
 
+    //Overrides the current decision, which then lasts as long as one the AI made itself
+    pub fn set_state(&mut self, state: AIState)
+    {
+        self.state = state;
+        self.time_since_decision = 0.0;
+    }
+
     fn decide_new_state(&mut self)
     {
         let mut rng = rand::thread_rng();
@@ -0,0 +1,33 @@
+use crate::component::component::Component;
+
+//Marks an entity whose Text is showing something it said. Once it expires the Text goes back to the entity's Nametag, if it has one.
+#[derive(Clone)]
+pub struct ChatBubble
+{
+    time_remaining: f32 //ms
+}
+
+impl ChatBubble
+{
+    pub fn new(duration: f32) -> Self
+    {
+        Self
+        {
+            time_remaining: duration
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32)
+    {
+        self.time_remaining -= delta_time;
+    }
+
+    pub fn is_expired(&self) -> bool
+    {
+        self.time_remaining <= 0.0
+    }
+}
+
+impl Component for ChatBubble
+{
+}
@@ -0,0 +1,62 @@
+use crate::component::component::Component;
+use crate::scene::scene::Scene;
+use crate::state::render_state::RenderState;
+
+//Gameplay hook for when the entity is picked by a click or tap.
+pub type ClickCallback = fn(&mut Scene, &mut RenderState, &ClickEvent);
+
+#[derive(Clone, Copy)]
+pub struct ClickEvent
+{
+    entity_uid: usize,
+    world_position: glm::Vec2
+}
+
+impl ClickEvent
+{
+    pub fn new(entity_uid: usize, world_position: glm::Vec2) -> Self
+    {
+        Self
+        {
+            entity_uid,
+            world_position
+        }
+    }
+
+    pub fn get_entity_uid(&self) -> usize
+    {
+        self.entity_uid
+    }
+
+    pub fn get_world_position(&self) -> &glm::Vec2
+    {
+        &self.world_position
+    }
+}
+
+//Marks an entity's sprite as something that can be picked. Entities without this are ignored when hit-testing clicks.
+#[derive(Clone)]
+pub struct Clickable
+{
+    on_click: ClickCallback
+}
+
+impl Clickable
+{
+    pub fn new(on_click: ClickCallback) -> Self
+    {
+        Self
+        {
+            on_click
+        }
+    }
+
+    pub fn get_on_click(&self) -> ClickCallback
+    {
+        self.on_click
+    }
+}
+
+impl Component for Clickable
+{
+}
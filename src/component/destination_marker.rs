@@ -0,0 +1,21 @@
+use crate::component::component::Component;
+
+//Marks a sprite that shows where the player is walking to. Hidden while there is no destination.
+#[derive(Clone)]
+pub struct DestinationMarker
+{
+}
+
+impl DestinationMarker
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+        }
+    }
+}
+
+impl Component for DestinationMarker
+{
+}
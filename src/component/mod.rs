@@ -2,4 +2,14 @@ pub mod component;
 pub mod component_buffer;
 pub mod physics_body;
 pub mod player_input;
-pub mod ai;
\ No newline at end of file
+pub mod ai;
+pub mod trigger;
+pub mod clickable;
+pub mod physics_debug_draw;
+pub mod platform;
+pub mod destination_marker;
+pub mod text_entry_display;
+pub mod peer_interpolation;
+pub mod predicted_movement;
+pub mod nametag;
+pub mod chat_bubble;
@@ -0,0 +1,28 @@
+use crate::component::component::Component;
+
+//What an entity's Text shows when it isn't showing a chat bubble
+#[derive(Clone)]
+pub struct Nametag
+{
+    content: String
+}
+
+impl Nametag
+{
+    pub fn new(content: &str) -> Self
+    {
+        Self
+        {
+            content: content.to_string()
+        }
+    }
+
+    pub fn get_content(&self) -> &String
+    {
+        &self.content
+    }
+}
+
+impl Component for Nametag
+{
+}
@@ -0,0 +1,227 @@
+use crate::component::component::Component;
+use crate::networking::message::MotionState;
+use crate::networking::server_connection::MAX_UPDATE_INTERVAL;
+use std::collections::VecDeque;
+
+static MAX_SNAPSHOTS : usize = 32;
+
+//Peers are shown this many of their own update gaps behind real time, so there is usually a newer snapshot to interpolate towards
+static INTERPOLATION_DELAY_GAPS : f32 = 2.0;
+
+//Weight of each new gap between snapshots in the smoothed gap
+static UPDATE_GAP_SMOOTHING : f32 = 0.25;
+
+//How far past the newest snapshot a peer keeps moving before it stops and waits, in milliseconds
+static MAX_EXTRAPOLATION : f32 = 100.0;
+
+#[derive(Clone)]
+struct PositionSnapshot
+{
+    time: f32, //Local clock time it was received, in milliseconds
+    position: glm::Vec2,
+    motion_state: Option<MotionState> //None if the peer or server doesn't send it
+}
+
+//Buffered positions of a remote peer. Peers are shown a little behind real time so there are
+//usually two snapshots to interpolate between; if updates stop, they carry on briefly and then stop.
+//How far behind depends on how often this peer's updates arrive, since senders slow down on bad connections.
+#[derive(Clone)]
+pub struct PeerInterpolation
+{
+    snapshots: VecDeque<PositionSnapshot>,
+    latest_velocity: glm::Vec2, //Per millisecond, between the two newest snapshots
+    update_gap: Option<f32>, //Smoothed time between snapshots, in milliseconds. None until there are two.
+    last_render_time: f32
+}
+
+impl PeerInterpolation
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            snapshots: VecDeque::new(),
+            latest_velocity: glm::vec2(0.0,0.0),
+            update_gap: None,
+            last_render_time: f32::NEG_INFINITY
+        }
+    }
+
+    pub fn push_snapshot(&mut self, time: f32, position: glm::Vec2, motion_state: Option<MotionState>)
+    {
+        if let Some(latest) = self.snapshots.back()
+        {
+            //Out of order or duplicate - nothing to interpolate towards
+            if time <= latest.time
+            {
+                return;
+            }
+
+            self.latest_velocity = (position - latest.position) / (time - latest.time);
+
+            //Longer gaps are a peer standing still and only sending keepalives, not a slow connection
+            let gap = (time - latest.time).min(MAX_UPDATE_INTERVAL);
+
+            self.update_gap = match self.update_gap
+            {
+                Some(g) => Some(g + (gap - g) * UPDATE_GAP_SMOOTHING),
+                None => Some(gap)
+            };
+        }
+
+        self.snapshots.push_back(PositionSnapshot { time, position, motion_state });
+
+        if self.snapshots.len() > MAX_SNAPSHOTS
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    //How far behind real time this peer should be shown, in milliseconds. 0.0 until its update rate is known.
+    pub fn get_interpolation_delay(&self) -> f32
+    {
+        self.update_gap.unwrap_or(0.0) * INTERPOLATION_DELAY_GAPS
+    }
+
+    //Position and velocity (in physics units) at the given time, or None before the first snapshot arrives.
+    //Snapshots older than needed for this time are dropped, so an earlier time than last sampled is treated as the same time.
+    pub fn sample(&mut self, render_time: f32) -> Option<(glm::Vec2,glm::Vec2)>
+    {
+        //A growing delay pauses the peer rather than moving it backwards
+        let render_time = render_time.max(self.last_render_time);
+        self.last_render_time = render_time;
+
+        while self.snapshots.len() >= 2 && self.snapshots[1].time <= render_time
+        {
+            self.snapshots.pop_front();
+        }
+
+        let from = self.snapshots.front()?.clone();
+
+        //Not enough history yet, hold at the oldest position
+        if render_time <= from.time
+        {
+            return Some((from.position, glm::vec2(0.0,0.0)));
+        }
+
+        match self.snapshots.get(1)
+        {
+            Some(to) => {
+                let t = (render_time - from.time) / (to.time - from.time);
+                let velocity = (to.position - from.position) / (to.time - from.time);
+
+                Some((glm::lerp(&from.position, &to.position, t), Self::to_physics_velocity(&velocity)))
+            },
+            None => {
+                //Past the newest snapshot - keep going the same way for a little while
+                let extrapolation = render_time - from.time;
+
+                if extrapolation >= MAX_EXTRAPOLATION
+                {
+                    return Some((from.position + self.latest_velocity * MAX_EXTRAPOLATION, glm::vec2(0.0,0.0)));
+                }
+
+                Some((from.position + self.latest_velocity * extrapolation, Self::to_physics_velocity(&self.latest_velocity)))
+            }
+        }
+    }
+
+    //How the peer was moving as of the last sampled time, if it says
+    pub fn get_motion_state(&self) -> Option<&MotionState>
+    {
+        self.snapshots.front()?.motion_state.as_ref()
+    }
+
+    //The physics system moves bodies by (delta_time / 5.0) * velocity
+    fn to_physics_velocity(velocity_per_ms: &glm::Vec2) -> glm::Vec2
+    {
+        velocity_per_ms * 5.0
+    }
+}
+
+impl Component for PeerInterpolation
+{
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn interpolates_between_snapshots()
+    {
+        let mut interpolation = PeerInterpolation::new();
+        interpolation.push_snapshot(0.0, glm::vec2(0.0,0.0), None);
+        interpolation.push_snapshot(100.0, glm::vec2(100.0,50.0), None);
+
+        let (position, velocity) = interpolation.sample(25.0).unwrap();
+
+        assert_eq!(position, glm::vec2(25.0,12.5));
+        assert_eq!(velocity, glm::vec2(5.0,2.5));
+    }
+
+    #[test]
+    fn extrapolates_for_a_limited_time_then_stops()
+    {
+        let mut interpolation = PeerInterpolation::new();
+        interpolation.push_snapshot(0.0, glm::vec2(0.0,0.0), None);
+        interpolation.push_snapshot(100.0, glm::vec2(100.0,0.0), None);
+
+        let (position, _) = interpolation.sample(150.0).unwrap();
+        assert_eq!(position, glm::vec2(150.0,0.0));
+
+        let (position, velocity) = interpolation.sample(1000.0).unwrap();
+        assert_eq!(position, glm::vec2(100.0 + MAX_EXTRAPOLATION,0.0));
+        assert_eq!(velocity, glm::vec2(0.0,0.0));
+    }
+
+    #[test]
+    fn delay_follows_how_often_updates_arrive()
+    {
+        let mut interpolation = PeerInterpolation::new();
+        assert_eq!(interpolation.get_interpolation_delay(), 0.0);
+
+        interpolation.push_snapshot(0.0, glm::vec2(0.0,0.0), None);
+        interpolation.push_snapshot(MAX_UPDATE_INTERVAL, glm::vec2(0.0,0.0), None);
+        assert_eq!(interpolation.get_interpolation_delay(), MAX_UPDATE_INTERVAL * INTERPOLATION_DELAY_GAPS);
+
+        //A keepalive from a peer standing still doesn't push it further behind
+        interpolation.push_snapshot(MAX_UPDATE_INTERVAL + 1000.0, glm::vec2(0.0,0.0), None);
+        assert_eq!(interpolation.get_interpolation_delay(), MAX_UPDATE_INTERVAL * INTERPOLATION_DELAY_GAPS);
+
+        //Faster updates bring it closer again
+        let mut time = MAX_UPDATE_INTERVAL + 1000.0;
+
+        for _ in 0..50
+        {
+            time += 50.0;
+            interpolation.push_snapshot(time, glm::vec2(0.0,0.0), None);
+        }
+
+        assert!((interpolation.get_interpolation_delay() - 50.0 * INTERPOLATION_DELAY_GAPS).abs() < 1.0);
+    }
+
+    #[test]
+    fn sampling_never_goes_back_in_time()
+    {
+        let mut interpolation = PeerInterpolation::new();
+        interpolation.push_snapshot(0.0, glm::vec2(0.0,0.0), None);
+        interpolation.push_snapshot(100.0, glm::vec2(100.0,0.0), None);
+
+        assert_eq!(interpolation.sample(50.0).unwrap().0, glm::vec2(50.0,0.0));
+        assert_eq!(interpolation.sample(20.0).unwrap().0, glm::vec2(50.0,0.0));
+    }
+
+    #[test]
+    fn holds_before_the_first_snapshot_and_ignores_stale_ones()
+    {
+        let mut interpolation = PeerInterpolation::new();
+        assert!(interpolation.sample(0.0).is_none());
+
+        interpolation.push_snapshot(100.0, glm::vec2(10.0,0.0), None);
+        interpolation.push_snapshot(50.0, glm::vec2(-500.0,0.0), None);
+
+        assert_eq!(interpolation.sample(0.0).unwrap().0, glm::vec2(10.0,0.0));
+    }
+}
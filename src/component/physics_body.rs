@@ -1,32 +1,59 @@
 use crate::component::component::Component;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum BodyType
+{
+    Static, //Never moves, e.g. scenery
+    Kinematic, //Moves by its velocity only, e.g. remote peers. Pushes dynamic bodies but is never pushed itself.
+    Dynamic //Moves by its velocity and is pushed out of other bodies, e.g. the player and NPCs
+}
+
+impl BodyType
+{
+    //How much of an overlap a body of this type absorbs when separated from a body of the other type
+    pub fn get_push_share(&self, other: &BodyType) -> f32
+    {
+        match (self, other)
+        {
+            (BodyType::Dynamic, BodyType::Dynamic) => 0.5,
+            (BodyType::Dynamic, _) => 1.0,
+            _ => 0.0
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PhysicsBody
 {
+    body_type: BodyType,
     position: glm::Vec2,
-    velocity: glm::Vec2
+    previous_position: glm::Vec2, //Position before the last physics step
+    velocity: glm::Vec2,
+    size: glm::Vec2,
+    ground_entity: Option<usize> //The platform this body is standing on, if any
 }
 
 impl PhysicsBody
 {
-    pub fn new() -> Self
+    //Size is the full width and height of the body's collider, centered on its position
+    pub fn new_with_body_type(body_type: BodyType, position: glm::Vec2, size: glm::Vec2) -> Self
     {
         Self
         {
-            position: glm::vec2(0.0,0.0),
-            velocity: glm::vec2(0.0,0.0)
+            body_type,
+            position,
+            previous_position: position,
+            velocity: glm::vec2(0.0,0.0),
+            size,
+            ground_entity: None
         }
-
     }
-    pub fn new_with_position(position: glm::Vec2) -> Self
-    {
-        Self
-        {
-            position: position,
-            velocity: glm::vec2(0.0,0.0)
-        }
 
+    pub fn get_body_type(&self) -> &BodyType
+    {
+        &self.body_type
     }
+
     pub fn get_position(&self) -> &glm::Vec2
     {
         &&self.position
@@ -38,6 +65,17 @@ impl PhysicsBody
         self.position.y = y;
     }
 
+    pub fn get_previous_position(&self) -> &glm::Vec2
+    {
+        &self.previous_position
+    }
+
+    pub fn set_previous_position(&mut self, x: f32, y: f32)
+    {
+        self.previous_position.x = x;
+        self.previous_position.y = y;
+    }
+
     pub fn get_velocity(&self) -> &glm::Vec2
     {
         &&self.velocity
@@ -48,6 +86,32 @@ impl PhysicsBody
         self.velocity.x = x;
         self.velocity.y = y;
     }
+
+    //Sets only the horizontal velocity, leaving vertical motion (jumping, falling) alone
+    pub fn set_velocity_x(&mut self, x: f32)
+    {
+        self.velocity.x = x;
+    }
+
+    pub fn get_size(&self) -> &glm::Vec2
+    {
+        &self.size
+    }
+
+    pub fn get_ground_entity(&self) -> Option<usize>
+    {
+        self.ground_entity
+    }
+
+    pub fn set_ground_entity(&mut self, ground_entity: Option<usize>)
+    {
+        self.ground_entity = ground_entity;
+    }
+
+    pub fn is_grounded(&self) -> bool
+    {
+        self.ground_entity.is_some()
+    }
 }
 
 impl Component for PhysicsBody
@@ -0,0 +1,48 @@
+use crate::component::component::Component;
+use crate::graphics::debug_lines::DebugLines;
+
+//Velocity is tiny in world units, so stretch the velocity line to make it readable
+pub const VELOCITY_LINE_SCALE : f32 = 100.0;
+
+//Debug visuals for a PhysicsBody: the collider outline and the velocity vector.
+//Added and removed by the physics debug draw system when debug drawing is toggled.
+#[derive(Clone)]
+pub struct PhysicsDebugDraw
+{
+    outline: DebugLines,
+    velocity: DebugLines
+}
+
+impl PhysicsDebugDraw
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            outline: DebugLines::new_rectangle_outline([0.0,1.0,0.0,1.0], 0.01),
+            velocity: DebugLines::new_ray([1.0,0.0,0.0,1.0], 0.01)
+        }
+    }
+
+    pub fn apply_to_renderables<F>(&mut self, mut functor: F)
+    where
+        F: FnMut(&mut DebugLines)
+    {
+        functor(&mut self.outline);
+        functor(&mut self.velocity);
+    }
+
+    pub fn get_outline(&self) -> &DebugLines
+    {
+        &self.outline
+    }
+
+    pub fn get_velocity(&self) -> &DebugLines
+    {
+        &self.velocity
+    }
+}
+
+impl Component for PhysicsDebugDraw
+{
+}
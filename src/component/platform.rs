@@ -0,0 +1,83 @@
+use crate::component::component::Component;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlatformType
+{
+    Solid, //Blocks from every side
+    OneWay //Can be jumped through from below and stood on from above
+}
+
+//Marks an entity's PhysicsBody as something dynamic bodies can stand on.
+//A platform with waypoints moves between them (its body should be kinematic) and carries anything standing on it.
+#[derive(Clone)]
+pub struct Platform
+{
+    platform_type: PlatformType,
+    waypoints: Vec<glm::Vec2>,
+    speed: f32,
+    next_waypoint: usize
+}
+
+impl Platform
+{
+    pub fn new(platform_type: PlatformType) -> Self
+    {
+        Self
+        {
+            platform_type,
+            waypoints: Vec::new(),
+            speed: 0.0,
+            next_waypoint: 0
+        }
+    }
+
+    pub fn new_moving(platform_type: PlatformType, waypoints: Vec<glm::Vec2>, speed: f32) -> Self
+    {
+        Self
+        {
+            platform_type,
+            waypoints,
+            speed,
+            next_waypoint: 0
+        }
+    }
+
+    pub fn get_platform_type(&self) -> &PlatformType
+    {
+        &self.platform_type
+    }
+
+    //Velocity needed to head towards the next waypoint without overshooting it this tick.
+    //Moves on to the following waypoint once the current one is reached.
+    pub fn get_velocity_towards_next_waypoint(&mut self, position: &glm::Vec2, delta_time: f32) -> glm::Vec2
+    {
+        if self.waypoints.is_empty() || delta_time <= 0.0
+        {
+            return glm::vec2(0.0,0.0);
+        }
+
+        let mut to_waypoint = self.waypoints[self.next_waypoint] - position;
+
+        if glm::length(&to_waypoint) < 0.01
+        {
+            self.next_waypoint = (self.next_waypoint + 1) % self.waypoints.len();
+            to_waypoint = self.waypoints[self.next_waypoint] - position;
+        }
+
+        let distance = glm::length(&to_waypoint);
+
+        if distance < 0.01
+        {
+            return glm::vec2(0.0,0.0);
+        }
+
+        //Same units as the physics system: position changes by (delta_time / 5.0) * velocity
+        let max_speed = distance / (delta_time / 5.0);
+
+        (to_waypoint / distance) * self.speed.min(max_speed)
+    }
+}
+
+impl Component for Platform
+{
+}
@@ -1,8 +1,18 @@
 use crate::component::component::Component;
 
+//What the player asked their possum to do on one tick, after walking to destinations is worked out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MovementInput
+{
+    pub velocity_x: f32,
+    pub jumped: bool //Only if it was on the ground to jump from
+}
+
 #[derive(Clone)]
 pub struct PlayerInput 
 {
+    destination: Option<glm::Vec2>, //World position to walk to, set by clicking/tapping
+    movement_input: MovementInput //As of the latest tick
 }
 
 impl PlayerInput 
@@ -11,9 +21,31 @@ impl PlayerInput
     {
         Self
         {
+            destination: None,
+            movement_input: MovementInput { velocity_x: 0.0, jumped: false }
         }
 
     }
+
+    pub fn get_destination(&self) -> &Option<glm::Vec2>
+    {
+        &self.destination
+    }
+
+    pub fn set_destination(&mut self, destination: Option<glm::Vec2>)
+    {
+        self.destination = destination;
+    }
+
+    pub fn get_movement_input(&self) -> &MovementInput
+    {
+        &self.movement_input
+    }
+
+    pub fn set_movement_input(&mut self, movement_input: MovementInput)
+    {
+        self.movement_input = movement_input;
+    }
 }
 
 impl Component for PlayerInput
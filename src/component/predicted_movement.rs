@@ -0,0 +1,140 @@
+use crate::component::component::Component;
+use crate::component::player_input::MovementInput;
+use std::collections::VecDeque;
+
+//Ticks of input kept for replaying. At 60 ticks a second this is a few seconds - corrections older than that can't be replayed.
+static MAX_HISTORY : usize = 256;
+
+//One tick of a locally simulated entity's input, and enough of how it ended to carry on from it
+#[derive(Clone, Debug, PartialEq)]
+pub struct PredictedTick
+{
+    sequence: u32,
+    input: MovementInput,
+    delta_time: f32, //As simulated, after any clamping
+    velocity_y: f32 //At the end of the tick. Corrections only say where the entity was, not how fast it was falling.
+}
+
+impl PredictedTick
+{
+    pub fn get_input(&self) -> &MovementInput
+    {
+        &self.input
+    }
+
+    pub fn get_delta_time(&self) -> f32
+    {
+        self.delta_time
+    }
+}
+
+//Input of a locally simulated entity (the player), numbered tick by tick. The latest number is sent with each update
+//so that the server can say which tick its corrections are for. A correction is applied by starting from the server's
+//position and simulating every tick of input the server hadn't seen yet, so when prediction was right nothing changes on screen.
+#[derive(Clone)]
+pub struct PredictedMovement
+{
+    sequence: u32,
+    history: VecDeque<PredictedTick>
+}
+
+impl PredictedMovement
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            sequence: 0,
+            history: VecDeque::new()
+        }
+    }
+
+    //Sequence number of the most recently recorded tick
+    pub fn get_sequence(&self) -> u32
+    {
+        self.sequence
+    }
+
+    pub fn record(&mut self, input: MovementInput, delta_time: f32, velocity_y: f32)
+    {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.history.push_back(PredictedTick { sequence: self.sequence, input, delta_time, velocity_y });
+
+        if self.history.len() > MAX_HISTORY
+        {
+            self.history.pop_front();
+        }
+    }
+
+    //The ticks the server hadn't seen as of the acknowledged one, to simulate again from the position it gives,
+    //and the vertical velocity to start them with. Ticks up to and including that one are forgotten since they won't be corrected again.
+    pub fn reconcile(&mut self, acknowledged_sequence: u32) -> (f32, Vec<PredictedTick>)
+    {
+        let mut velocity_y = 0.0;
+
+        //Compared with wrapping arithmetic so that sequence numbers can roll over
+        while let Some(tick) = self.history.front()
+        {
+            if (acknowledged_sequence.wrapping_sub(tick.sequence) as i32) < 0
+            {
+                break;
+            }
+
+            velocity_y = tick.velocity_y;
+            self.history.pop_front();
+        }
+
+        (velocity_y, self.history.iter().cloned().collect())
+    }
+}
+
+impl Component for PredictedMovement
+{
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn walking(velocity_x: f32) -> MovementInput
+    {
+        MovementInput { velocity_x, jumped: false }
+    }
+
+    #[test]
+    fn hands_back_unacknowledged_input_to_replay()
+    {
+        let mut prediction = PredictedMovement::new();
+
+        for tick in 0..5
+        {
+            prediction.record(walking(tick as f32), 16.0, -(tick as f32));
+        }
+
+        //Server has processed 3 ticks, so the last two are replayed starting from how the third ended
+        let (velocity_y, ticks) = prediction.reconcile(3);
+        assert_eq!(velocity_y, -2.0);
+        assert_eq!(ticks.iter().map(|t| t.get_input().velocity_x).collect::<Vec<f32>>(), vec![3.0, 4.0]);
+
+        //Acknowledged ticks are gone, so a later correction only replays what's left
+        prediction.record(walking(5.0), 16.0, 0.0);
+        let (_, ticks) = prediction.reconcile(5);
+        assert_eq!(ticks.len(), 1);
+        assert_eq!(ticks[0].get_input().velocity_x, 5.0);
+    }
+
+    #[test]
+    fn handles_sequence_wrap_around()
+    {
+        let mut prediction = PredictedMovement::new();
+        prediction.sequence = u32::MAX - 1;
+
+        prediction.record(walking(1.0), 16.0, 0.0); //u32::MAX
+        prediction.record(walking(2.0), 16.0, 0.0); //0
+        prediction.record(walking(3.0), 16.0, 0.0); //1
+
+        let (_, ticks) = prediction.reconcile(u32::MAX);
+        assert_eq!(ticks.iter().map(|t| t.get_input().velocity_x).collect::<Vec<f32>>(), vec![2.0, 3.0]);
+    }
+}
@@ -0,0 +1,21 @@
+use crate::component::component::Component;
+
+//Marks the entity whose Text shows the line the player is typing. It has no Text while nothing is being typed.
+#[derive(Clone)]
+pub struct TextEntryDisplay
+{
+}
+
+impl TextEntryDisplay
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+        }
+    }
+}
+
+impl Component for TextEntryDisplay
+{
+}
@@ -0,0 +1,150 @@
+use crate::component::component::Component;
+use crate::scene::scene::Scene;
+use crate::state::render_state::RenderState;
+use crate::util::util::rectangles_overlap;
+use std::collections::HashSet;
+
+//Content hooks for triggers. The scene is handed back so that callbacks can add or remove components in response.
+pub type TriggerCallback = fn(&mut Scene, &mut RenderState, &TriggerEvent);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TriggerEventType
+{
+    Enter,
+    Stay,
+    Exit
+}
+
+#[derive(Clone, Copy)]
+pub struct TriggerEvent
+{
+    event_type: TriggerEventType,
+    trigger_entity_uid: usize,
+    other_entity_uid: usize
+}
+
+impl TriggerEvent
+{
+    pub fn new(event_type: TriggerEventType, trigger_entity_uid: usize, other_entity_uid: usize) -> Self
+    {
+        Self
+        {
+            event_type,
+            trigger_entity_uid,
+            other_entity_uid
+        }
+    }
+
+    pub fn get_event_type(&self) -> &TriggerEventType
+    {
+        &self.event_type
+    }
+
+    pub fn get_trigger_entity_uid(&self) -> usize
+    {
+        self.trigger_entity_uid
+    }
+
+    pub fn get_other_entity_uid(&self) -> usize
+    {
+        self.other_entity_uid
+    }
+}
+
+//A non-solid area which tracks the PhysicsBody entities overlapping it.
+#[derive(Clone)]
+pub struct Trigger
+{
+    position: glm::Vec2,
+    size: glm::Vec2,
+    occupants: HashSet<usize>,
+    on_enter: Option<TriggerCallback>,
+    on_stay: Option<TriggerCallback>,
+    on_exit: Option<TriggerCallback>
+}
+
+impl Trigger
+{
+    pub fn new(position: glm::Vec2, size: glm::Vec2) -> Self
+    {
+        Self
+        {
+            position,
+            size,
+            occupants: HashSet::new(),
+            on_enter: None,
+            on_stay: None,
+            on_exit: None
+        }
+    }
+
+    pub fn set_on_enter(&mut self, callback: TriggerCallback)
+    {
+        self.on_enter = Some(callback);
+    }
+
+    pub fn set_on_stay(&mut self, callback: TriggerCallback)
+    {
+        self.on_stay = Some(callback);
+    }
+
+    pub fn set_on_exit(&mut self, callback: TriggerCallback)
+    {
+        self.on_exit = Some(callback);
+    }
+
+    //Given (entity uid, position, size) for every body in the scene, update who is inside the trigger.
+    //Returns the events that have a callback attached, in the order they should be dispatched.
+    pub fn update(&mut self, trigger_entity_uid: usize, bodies: &[(usize,glm::Vec2,glm::Vec2)]) -> Vec<(TriggerCallback,TriggerEvent)>
+    {
+        let mut events = Vec::new();
+        let mut overlapping = HashSet::new();
+
+        for (entity_uid, position, size) in bodies
+        {
+            if *entity_uid == trigger_entity_uid
+            {
+                continue;
+            }
+
+            if !rectangles_overlap(&self.position, &self.size, position, size)
+            {
+                continue;
+            }
+
+            overlapping.insert(*entity_uid);
+
+            let (event_type, callback) = if self.occupants.insert(*entity_uid)
+            {
+                (TriggerEventType::Enter, self.on_enter)
+            } else
+            {
+                (TriggerEventType::Stay, self.on_stay)
+            };
+
+            if let Some(c) = callback
+            {
+                events.push((c, TriggerEvent::new(event_type, trigger_entity_uid, *entity_uid)));
+            }
+        }
+
+        //Anyone who was inside last tick but isn't now has left (including entities that were removed)
+        let departed : Vec<usize> = self.occupants.difference(&overlapping).cloned().collect();
+
+        for entity_uid in departed
+        {
+            self.occupants.remove(&entity_uid);
+
+            if let Some(c) = self.on_exit
+            {
+                events.push((c, TriggerEvent::new(TriggerEventType::Exit, trigger_entity_uid, entity_uid)));
+            }
+        }
+
+        events
+    }
+}
+
+impl Component for Trigger
+{
+}
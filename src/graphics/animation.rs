@@ -2,7 +2,7 @@ use crate::component::component::Component;
 use crate::graphics::renderable::Renderable;
 use std::collections::HashMap;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 #[derive(Eq, Hash, PartialEq)]
 pub enum AnimationState 
 {
@@ -12,8 +12,62 @@ pub enum AnimationState
     WalkingLeft
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Facing
+{
+    Left,
+    Right
+}
+
 impl AnimationState 
 {
+    //Sent over the network, so existing ids must not change
+    pub fn id(&self) -> u8
+    {
+        match self
+        {
+            AnimationState::FacingLeft => 0,
+            AnimationState::FacingRight => 1,
+            AnimationState::WalkingRight => 2,
+            AnimationState::WalkingLeft => 3
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<AnimationState>
+    {
+        match id
+        {
+            0 => Some(AnimationState::FacingLeft),
+            1 => Some(AnimationState::FacingRight),
+            2 => Some(AnimationState::WalkingRight),
+            3 => Some(AnimationState::WalkingLeft),
+            _ => None
+        }
+    }
+
+    pub fn get_facing(&self) -> Facing
+    {
+        match self
+        {
+            AnimationState::FacingLeft | AnimationState::WalkingLeft => Facing::Left,
+            AnimationState::FacingRight | AnimationState::WalkingRight => Facing::Right
+        }
+    }
+
+    //Standing still facing the given way
+    pub fn idle(facing: Facing) -> AnimationState
+    {
+        match facing
+        {
+            Facing::Left => AnimationState::FacingLeft,
+            Facing::Right => AnimationState::FacingRight
+        }
+    }
+
+    pub fn is_walking(&self) -> bool
+    {
+        matches!(self, AnimationState::WalkingLeft | AnimationState::WalkingRight)
+    }
 }
 
 #[derive(Clone)]
@@ -75,19 +129,24 @@ impl<T: Renderable> Animation<T>
 
     pub fn get_renderable_uid(&self) -> Option<u32> 
     {
-        let renderables_for_state = match self.renderable_map.get(&self.current_animation_state)
+        let renderable = match self.get_current_renderable()
         {
-            Some(r) => r,
+            Some(u) => u,
             None => {return None;}
         };
 
-        let renderable = match renderables_for_state.get(self.current_renderable_index)
+        Some(renderable.get_renderable_uid())
+    }
+
+    pub fn get_current_renderable(&self) -> Option<&T>
+    {
+        let renderables_for_state = match self.renderable_map.get(&self.current_animation_state)
         {
-            Some(u) => u,
+            Some(r) => r,
             None => {return None;}
         };
 
-        Some(renderable.get_renderable_uid())
+        renderables_for_state.get(self.current_renderable_index)
     }
 
     pub fn step_animation(&mut self)
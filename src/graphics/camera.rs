@@ -1,15 +1,26 @@
 use nalgebra_glm::TMat4;
 
+//Zoom is in world units per canvas pixel, so smaller values are more zoomed in
+static DEFAULT_MIN_ZOOM : f32 = 0.125;
+static DEFAULT_MAX_ZOOM : f32 = 4.0;
+
 pub struct Camera
 {
     view_matrix: TMat4<f32>,
     projection_matrix: TMat4<f32>,
+    inverse_view_projection_matrix: TMat4<f32>,
     dirty : bool,
     canvas_width : u32,
     canvas_height : u32,
     eye: glm::Vec3,
     target: glm::Vec3,
-    zoom: f32
+    zoom: f32, //What is actually used, after limits and snapping
+    requested_zoom: f32, //Kept separately so small zoom changes can build up to the next snapped level
+    base_zoom: f32, //What suits the canvas size. Zooming by the user is kept relative to this when the canvas resizes.
+    min_zoom: f32,
+    max_zoom: f32,
+    pixel_snapping: bool,
+    focus_offset: glm::Vec2 //Where the camera looks relative to what it follows, moved by zooming towards the cursor
 }
 
 impl Camera
@@ -20,12 +31,19 @@ impl Camera
         {
             view_matrix: glm::Mat4::identity(), 
             projection_matrix: glm::Mat4::identity(),
+            inverse_view_projection_matrix: glm::Mat4::identity(),
             dirty: true,
             canvas_width,
             canvas_height,
             eye: glm::vec3(0.0,0.0,1.0),
             target: glm::vec3(0.0,0.0,0.0),
-            zoom: 1.0
+            zoom: 1.0,
+            requested_zoom: 1.0,
+            base_zoom: 1.0,
+            min_zoom: DEFAULT_MIN_ZOOM,
+            max_zoom: DEFAULT_MAX_ZOOM,
+            pixel_snapping: true,
+            focus_offset: glm::vec2(0.0,0.0)
         }
     }
 
@@ -56,15 +74,125 @@ impl Camera
     {
         self.update_view_matrix();
         self.update_projection_matrix();
+        self.inverse_view_projection_matrix = glm::inverse(&self.get_view_projection_matrix());
         self.dirty = false;
     }
 
+    //Convert a position on the canvas (0..1 on each axis, from the bottom left) to a world position.
+    //Uses the matrices from the last recalculation, which is what is currently on screen.
+    pub fn canvas_ratio_to_world_position(&self, canvas_ratio_x: f32, canvas_ratio_y: f32) -> glm::Vec2
+    {
+        let ndc = glm::vec4(canvas_ratio_x * 2.0 - 1.0, canvas_ratio_y * 2.0 - 1.0, 0.0, 1.0);
+        let world = self.inverse_view_projection_matrix * ndc;
+
+        glm::vec2(world.x,world.y)
+    }
+
     pub fn set_zoom(&mut self, zoom: f32)
     {
-        self.zoom = zoom;
+        self.requested_zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+        self.zoom = self.snap_zoom(self.requested_zoom);
+        self.focus_offset = glm::vec2(0.0,0.0);
+        self.dirty = true;
+    }
+
+    //Multiplies the zoom (> 1.0 zooms out) while keeping the world position under the given canvas position in place
+    pub fn zoom_towards(&mut self, factor: f32, canvas_ratio_x: f32, canvas_ratio_y: f32)
+    {
+        if factor <= 0.0
+        {
+            return;
+        }
+
+        let old_zoom = self.zoom;
+
+        self.requested_zoom = (self.requested_zoom * factor).clamp(self.min_zoom, self.max_zoom);
+        self.zoom = self.snap_zoom(self.requested_zoom);
+
+        if self.zoom == old_zoom
+        {
+            return;
+        }
+
+        //The cursor's offset from the canvas center in pixels covers a different amount of world at the new zoom.
+        //Shift the focus by the difference so the same world position stays under the cursor.
+        let canvas_size = glm::vec2(self.canvas_width as f32, self.canvas_height as f32);
+        let cursor_from_center = glm::vec2(canvas_ratio_x - 0.5, canvas_ratio_y - 0.5).component_mul(&canvas_size);
+        let focus_offset = self.focus_offset + cursor_from_center * (old_zoom - self.zoom);
+
+        self.set_focus_offset(focus_offset);
+    }
+
+    //Changes the zoom that suits the canvas, keeping however far the user has zoomed in or out on top of it
+    pub fn set_base_zoom(&mut self, base_zoom: f32)
+    {
+        if base_zoom <= 0.0
+        {
+            return;
+        }
+
+        let user_zoom = self.requested_zoom / self.base_zoom;
+
+        self.base_zoom = base_zoom;
+        self.requested_zoom = (base_zoom * user_zoom).clamp(self.min_zoom, self.max_zoom);
+        self.zoom = self.snap_zoom(self.requested_zoom);
+        self.set_focus_offset(self.focus_offset);
+    }
+
+    fn set_focus_offset(&mut self, focus_offset: glm::Vec2)
+    {
+        //Don't let the focus drift so far that whatever the camera follows goes off screen
+        let max_offset = glm::vec2(self.canvas_width as f32, self.canvas_height as f32) * self.zoom * 0.5;
+        self.focus_offset = glm::vec2(focus_offset.x.clamp(-max_offset.x, max_offset.x), focus_offset.y.clamp(-max_offset.y, max_offset.y));
+
+        self.dirty = true;
+    }
+
+    pub fn set_zoom_limits(&mut self, min_zoom: f32, max_zoom: f32)
+    {
+        if min_zoom <= 0.0 || min_zoom > max_zoom
+        {
+            return;
+        }
+
+        self.min_zoom = min_zoom;
+        self.max_zoom = max_zoom;
+        self.set_zoom(self.requested_zoom);
+    }
+
+    //With snapping on, every world unit covers a whole number of canvas pixels (or the other way around when zoomed out)
+    //so that pixel art stays crisp
+    pub fn set_pixel_snapping(&mut self, pixel_snapping: bool)
+    {
+        self.pixel_snapping = pixel_snapping;
+        self.zoom = self.snap_zoom(self.requested_zoom);
         self.dirty = true;
     }
 
+    fn snap_zoom(&self, zoom: f32) -> f32
+    {
+        if !self.pixel_snapping
+        {
+            return zoom;
+        }
+
+        let snapped = if zoom < 1.0
+        {
+            1.0 / (1.0 / zoom).round()
+        } else
+        {
+            zoom.round()
+        };
+
+        //Snapping can land outside the limits when they aren't integer scales themselves
+        if snapped < self.min_zoom || snapped > self.max_zoom
+        {
+            return zoom;
+        }
+
+        snapped
+    }
+
     pub fn set_canvas_dimensions(&mut self, x: u32, y: u32)
     {
         self.canvas_width = x;
@@ -74,6 +202,7 @@ impl Camera
 
     pub fn set_camera_world_position(&mut self, position: &glm::Vec2)
     {
+        let position = position + self.focus_offset;
         let screen_translation = glm::vec2(position.x - (self.canvas_width as f32 * self.zoom * 0.5),position.y - (self.canvas_height as f32 * self.zoom * 0.5)); 
 
         self.eye.x = screen_translation.x;
@@ -83,4 +212,92 @@ impl Camera
         self.target.y = screen_translation.y;
         self.dirty = true;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn follow(camera: &mut Camera, position: &glm::Vec2)
+    {
+        camera.set_camera_world_position(position);
+        camera.recalculate();
+    }
+
+    #[test]
+    fn zoom_snaps_to_whole_and_fractional_pixel_scales()
+    {
+        let camera = Camera::new(800, 600);
+
+        assert_eq!(camera.snap_zoom(2.4), 2.0);
+        assert_eq!(camera.snap_zoom(2.6), 3.0);
+        assert_eq!(camera.snap_zoom(0.3), 1.0 / 3.0);
+        assert_eq!(camera.snap_zoom(0.7), 1.0);
+    }
+
+    #[test]
+    fn snapping_outside_the_limits_keeps_the_requested_zoom()
+    {
+        let mut camera = Camera::new(800, 600);
+        camera.set_zoom_limits(0.4, 0.45);
+
+        //The nearest whole scale, 1/2, is above the max
+        assert_eq!(camera.snap_zoom(0.42), 0.42);
+
+        camera.set_pixel_snapping(false);
+        assert_eq!(camera.snap_zoom(2.4), 2.4);
+    }
+
+    #[test]
+    fn zoom_is_clamped_to_the_limits()
+    {
+        let mut camera = Camera::new(800, 600);
+
+        camera.set_zoom(100.0);
+        assert_eq!(camera.zoom, DEFAULT_MAX_ZOOM);
+
+        for _ in 0..20
+        {
+            camera.zoom_towards(0.5, 0.5, 0.5);
+        }
+
+        assert_eq!(camera.zoom, DEFAULT_MIN_ZOOM);
+
+        camera.set_zoom_limits(0.5, 2.0);
+        assert_eq!(camera.zoom, 0.5);
+    }
+
+    #[test]
+    fn zooming_towards_the_cursor_keeps_what_is_under_it()
+    {
+        let mut camera = Camera::new(800, 600);
+        let followed = glm::vec2(100.0, 50.0);
+        follow(&mut camera, &followed);
+
+        let before = camera.canvas_ratio_to_world_position(0.7, 0.4);
+
+        camera.zoom_towards(2.0, 0.7, 0.4);
+        follow(&mut camera, &followed);
+
+        let after = camera.canvas_ratio_to_world_position(0.7, 0.4);
+        assert!(glm::distance(&before, &after) < 0.01, "{:?} moved to {:?}", before, after);
+    }
+
+    #[test]
+    fn resizing_keeps_the_users_zoom_and_focus()
+    {
+        let mut camera = Camera::new(800, 600);
+        camera.set_base_zoom(1.0);
+        camera.zoom_towards(2.0, 0.7, 0.4);
+
+        let focus_offset = camera.focus_offset;
+        assert_ne!(focus_offset, glm::vec2(0.0,0.0));
+
+        camera.set_canvas_dimensions(1200, 900);
+        camera.set_base_zoom(1.5);
+
+        assert_eq!(camera.zoom, 3.0);
+        assert_eq!(camera.focus_offset, focus_offset);
+    }
+}
@@ -0,0 +1,124 @@
+use web_sys::WebGl2RenderingContext;
+
+use crate::graphics::renderable::Renderable;
+use crate::graphics::vertex_layout::{VertexLayout,VertexLayoutElement};
+use crate::RenderState;
+
+//Untextured line segments, used for debug visuals.
+//Segments are defined in unit space and sized/rotated using the transform, so the vertex data never has to change.
+#[derive(Clone)]
+pub struct DebugLines {
+    renderable_uid: u32,
+    points: Vec<[f32;2]>, //Each pair of points is one segment
+    color: [f32;4],
+    size: [i32;2],
+    starting_z: f32
+}
+
+impl DebugLines
+{
+    //A 1x1 square outline centered on 0,0
+    pub fn new_rectangle_outline(color: [f32;4], starting_z: f32) -> Self
+    {
+        Self
+        {
+            renderable_uid: 0, //TODO: better default
+            points: vec![
+                [-0.5,-0.5],[0.5,-0.5],
+                [0.5,-0.5],[0.5,0.5],
+                [0.5,0.5],[-0.5,0.5],
+                [-0.5,0.5],[-0.5,-0.5],
+            ],
+            color,
+            size: [1,1],
+            starting_z
+        }
+    }
+
+    //A line of length 1 along the x axis, starting at 0,0
+    pub fn new_ray(color: [f32;4], starting_z: f32) -> Self
+    {
+        Self
+        {
+            renderable_uid: 0, //TODO: better default
+            points: vec![
+                [0.0,0.0],[1.0,0.0]
+            ],
+            color,
+            size: [1,1],
+            starting_z
+        }
+    }
+}
+
+impl Renderable for DebugLines
+{
+    fn get_vertex_layout() -> super::vertex_layout::VertexLayout
+    {
+       VertexLayout::new(vec![
+            VertexLayoutElement { location: 0, size: 3}, //Position
+            VertexLayoutElement { location: 1, size: 1}, //Model matrix transform index
+            VertexLayoutElement { location: 2, size: 2}, //Texture coords (unused)
+            VertexLayoutElement { location: 3, size: 1}, //Texture index (always -1, meaning untextured)
+            VertexLayoutElement { location: 4, size: 4}, //Color
+       ])
+    }
+
+    fn get_vertices(&self, _render_state: &RenderState, model_matrix_transform_index: u32) -> Vec<f32>
+    {
+        let mut vertices = Vec::<f32>::new();
+
+        for point in &self.points
+        {
+            vertices.extend_from_slice(&[
+                point[0],point[1],0.0,
+                model_matrix_transform_index as f32,
+                0.0,0.0,
+                -1.0,
+                self.color[0],self.color[1],self.color[2],self.color[3]
+            ]);
+        }
+
+        vertices
+    }
+
+    fn get_indices(&self) -> Vec<u32>
+    {
+        (0..self.points.len() as u32).collect()
+    }
+
+    fn get_draw_type() -> u32
+    {
+        WebGl2RenderingContext::LINES
+    }
+
+    fn get_size(&self) -> &[i32;2]
+    {
+        &self.size
+    }
+
+    fn get_starting_world_position(&self) -> Option<&glm::Vec2>
+    {
+        None
+    }
+
+    fn get_starting_scale(&self) -> Option<&glm::Vec2>
+    {
+        None
+    }
+
+    fn get_starting_z(&self) -> Option<f32>
+    {
+        Some(self.starting_z)
+    }
+
+    fn get_renderable_uid(&self) -> u32
+    {
+        self.renderable_uid
+    }
+
+    fn set_renderable_uid(&mut self, uid: u32)
+    {
+        self.renderable_uid = uid;
+    }
+}
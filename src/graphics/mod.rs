@@ -10,4 +10,5 @@ pub mod transform;
 pub mod draw_batch;
 pub mod font;
 pub mod text;
-pub mod animation;
\ No newline at end of file
+pub mod animation;
+pub mod debug_lines;
\ No newline at end of file
@@ -48,6 +48,19 @@ impl Sprite
     }
 }
 
+impl Sprite
+{
+    pub fn get_texture_coordinates(&self) -> [i32;2]
+    {
+        self.texture_coordinates
+    }
+
+    pub fn get_texture_index(&self) -> u32
+    {
+        self.texture_index
+    }
+}
+
 impl Component for Sprite
 {
 }
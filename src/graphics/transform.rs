@@ -45,6 +45,16 @@ impl Transform
         &&self.translation.z
     }
 
+    pub fn get_translation(&self) -> &glm::Vec3
+    {
+        &self.translation
+    }
+
+    pub fn get_scale(&self) -> &glm::Vec3
+    {
+        &self.scale
+    }
+
     pub fn set_scale(&mut self, scale: &glm::Vec2)
     {
         self.scale.x = scale.x;
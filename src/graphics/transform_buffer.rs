@@ -145,6 +145,36 @@ impl TransformBuffer
         Some(self.transforms[*index as usize].get_z())
     }
 
+    pub fn get_translation(&self, uid: &u32) -> Option<&glm::Vec3>
+    {
+        let index = match self.uid_to_index_map.get(uid)
+        {
+            Some(i) => i,
+            None => { return None; }
+        };
+
+        match self.transforms.get(*index as usize)
+        {
+            Some(t) => Some(t.get_translation()),
+            None => None
+        }
+    }
+
+    pub fn get_scale(&self, uid: &u32) -> Option<&glm::Vec3>
+    {
+        let index = match self.uid_to_index_map.get(uid)
+        {
+            Some(i) => i,
+            None => { return None; }
+        };
+
+        match self.transforms.get(*index as usize)
+        {
+            Some(t) => Some(t.get_scale()),
+            None => None
+        }
+    }
+
     pub fn set_rotation(&mut self, uid: &u32, rotation: f32)
     {
         let index = match self.uid_to_index_map.get(uid)
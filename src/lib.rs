@@ -19,6 +19,9 @@ use web_sys::{Document, HtmlImageElement};
 
 use state::input_state::InputState;
 use state::render_state::RenderState;
+use state::action_map::{Action, InputBinding};
+use state::input_recording::InputRecording;
+use util::logging::log;
 use scene::scene::Scene;
 use networking::server_connection::ServerConnection;
 use networking::message::Message;
@@ -36,14 +39,22 @@ pub struct Game
 #[wasm_bindgen]
 impl Game
 {
-    pub fn new(document: &Document) -> Self
+    //Connects to the given ws:// or wss:// endpoint straight away, or starts offline if there isn't one
+    pub fn new(document: &Document, endpoint: Option<String>) -> Self
     {
+        let mut server_connection = ServerConnection::new();
+
+        if let Some(e) = endpoint
+        {
+            server_connection.connect(&e);
+        }
+
         Self
         {
             scene: Scene::new(),
             render_state: RenderState::new(document),
             input_state: InputState::new(),
-            server_connection: ServerConnection::new()
+            server_connection
         }
     }
 
@@ -65,22 +76,137 @@ impl Game
 
     pub fn run_systems(&mut self, delta_time: f32)
     {
+        //Replays run with the timing they were recorded with
+        let delta_time = self.input_state.get_playback_delta_time().unwrap_or(delta_time);
+
         run_systems(&mut self.scene, &mut self.render_state,&mut self.input_state, &mut self.server_connection, delta_time);
     }
 
-    pub fn process_keypress_event(&mut self, pressed: bool, code : &str)
+    pub fn process_keypress_event(&mut self, pressed: bool, code : &str, key: &str)
+    {
+        self.input_state.process_input(pressed,code,key);
+    }
+
+    //While true, key presses are typed into the chat line rather than controlling the player
+    pub fn is_text_entry_active(&self) -> bool
+    {
+        self.input_state.is_text_entry_active()
+    }
+
+    pub fn process_click_event(&mut self, start_or_end: bool, x: i32, y: i32, button: i16, pointer_id: i32)
+    {
+        self.input_state.process_click(start_or_end,x,y,button,pointer_id);
+    }
+
+    //The browser stopped sending events for this pointer without it being released, e.g. it took over the touch to scroll
+    pub fn process_pointer_cancel_event(&mut self, pointer_id: i32)
+    {
+        self.input_state.process_pointer_cancel(pointer_id);
+    }
+
+    pub fn process_mouse_move_event(&mut self, x: i32, y: i32, pointer_id: i32)
+    {
+        self.input_state.process_mouse_move(x,y,pointer_id);
+    }
+
+    pub fn process_scroll_event(&mut self, delta_x: f32, delta_y: f32)
+    {
+        self.input_state.process_scroll(delta_x, delta_y);
+    }
+
+    pub fn process_gamepad_state(&mut self, axes: &[f32], buttons: &[f32])
+    {
+        self.input_state.process_gamepad_state(axes, buttons);
+    }
+
+    pub fn start_input_recording(&mut self)
+    {
+        self.input_state.start_recording();
+    }
+
+    //Returns the serialized recording, empty if nothing was being recorded
+    pub fn stop_input_recording(&mut self) -> Vec<u8>
+    {
+        match self.input_state.stop_recording()
+        {
+            Some(recording) => recording.to_bytes(),
+            None => Vec::new()
+        }
+    }
+
+    //Returns false if the bytes aren't a valid recording
+    pub fn start_input_playback(&mut self, recording: &[u8]) -> bool
+    {
+        match InputRecording::from_bytes(recording)
+        {
+            Some(r) => {
+                self.input_state.start_playback(r);
+                true
+            },
+            None => {
+                log("Invalid input recording");
+                false
+            }
+        }
+    }
+
+    pub fn is_input_playback_active(&self) -> bool
+    {
+        self.input_state.is_playing_back()
+    }
+
+    pub fn bind_key(&mut self, action_name: &str, code: &str)
+    {
+        self.bind(action_name, InputBinding::Key(code.to_string()));
+    }
+
+    pub fn bind_pointer_button(&mut self, action_name: &str, button: i16)
+    {
+        self.bind(action_name, InputBinding::PointerButton(button));
+    }
+
+    pub fn bind_gamepad_button(&mut self, action_name: &str, button: u32)
     {
-        self.input_state.process_input(pressed,code);
+        self.bind(action_name, InputBinding::GamepadButton(button));
     }
 
-    pub fn process_click_event(&mut self, start_or_end: bool, x: i32, y: i32)
+    pub fn bind_gamepad_axis(&mut self, action_name: &str, axis: u32, positive: bool)
     {
-        self.input_state.process_click(start_or_end,x,y);
+        if positive
+        {
+            self.bind(action_name, InputBinding::GamepadAxisPositive(axis));
+        } else
+        {
+            self.bind(action_name, InputBinding::GamepadAxisNegative(axis));
+        }
+    }
+
+    pub fn clear_bindings(&mut self, action_name: &str)
+    {
+        let action = match Action::from_name(action_name)
+        {
+            Some(a) => a,
+            None => {
+                log(&format!("Tried to clear bindings for unknown action {}",action_name));
+                return;
+            }
+        };
+
+        self.input_state.get_mut_action_map().clear_bindings(action);
     }
 
-    pub fn process_mouse_move_event(&mut self, x: i32, y: i32)
+    fn bind(&mut self, action_name: &str, binding: InputBinding)
     {
-        self.input_state.process_mouse_move(x,y);
+        let action = match Action::from_name(action_name)
+        {
+            Some(a) => a,
+            None => {
+                log(&format!("Tried to bind unknown action {}",action_name));
+                return;
+            }
+        };
+
+        self.input_state.get_mut_action_map().bind(action, binding);
     }
 
     pub fn set_canvas_dimensions(&mut self, x: u32, y: u32)
@@ -89,6 +215,63 @@ impl Game
         self.input_state.set_canvas_dimensions(x,y);
     }
 
+    //Zoom is in world units per canvas pixel
+    pub fn set_camera_zoom_limits(&mut self, min_zoom: f32, max_zoom: f32)
+    {
+        self.render_state.set_camera_zoom_limits(min_zoom, max_zoom);
+    }
+
+    pub fn set_camera_pixel_snapping(&mut self, pixel_snapping: bool)
+    {
+        self.render_state.set_camera_pixel_snapping(pixel_snapping);
+    }
+
+    pub fn set_physics_debug_draw(&mut self, enabled: bool)
+    {
+        self.render_state.set_physics_debug_draw(enabled);
+    }
+
+    //Leaves the current server, if any, and joins this one
+    pub fn connect(&mut self, endpoint: &str)
+    {
+        self.server_connection.connect(endpoint);
+    }
+
+    pub fn disconnect(&mut self)
+    {
+        self.server_connection.disconnect();
+    }
+
+    pub fn get_server_endpoint(&self) -> Option<String>
+    {
+        self.server_connection.get_endpoint().clone()
+    }
+
+    //The least far behind real time other players are shown, in milliseconds. Higher is smoother on bad connections.
+    //Players whose updates arrive less often are shown further behind regardless.
+    pub fn set_interpolation_delay(&mut self, interpolation_delay: f32)
+    {
+        self.server_connection.set_interpolation_delay(interpolation_delay);
+    }
+
+    //One of Offline, Connecting, Open, Closed or Backoff
+    pub fn get_connection_state(&self) -> String
+    {
+        self.server_connection.get_state().name().to_string()
+    }
+
+    //What the page should show about the server connection, including why it was refused if it was
+    pub fn get_connection_status(&self) -> String
+    {
+        self.server_connection.get_status()
+    }
+
+    //Smoothed round trip time to the server in milliseconds, if it answers pings
+    pub fn get_round_trip_time(&self) -> Option<f32>
+    {
+        self.server_connection.get_round_trip_time()
+    }
+
     pub fn send_chat_message(&mut self, content: String)
     {
         let message = Message::new_chat_message(content);
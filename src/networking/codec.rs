@@ -0,0 +1,178 @@
+use std::convert::TryInto;
+use std::fmt;
+
+//Why some bytes couldn't be decoded into a message
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError
+{
+    Truncated { needed: usize, remaining: usize },
+    UnknownMessageType(i32),
+    InvalidUtf8,
+    InvalidPeerId,
+    TrailingBytes(usize) //Decoded fine, but this many bytes were left over
+}
+
+impl fmt::Display for DecodeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            DecodeError::Truncated { needed, remaining } => write!(f, "truncated, needed {} more bytes but only {} were left", needed, remaining),
+            DecodeError::UnknownMessageType(id) => write!(f, "unknown message type {}", id),
+            DecodeError::InvalidUtf8 => write!(f, "text is not valid utf-8"),
+            DecodeError::InvalidPeerId => write!(f, "peer id is not a uuid"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} unexpected bytes at the end", count)
+        }
+    }
+}
+
+//Which way a message is travelling. Messages from the server are prefixed with the id of the client they're about.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction
+{
+    ToServer,
+    ToClient
+}
+
+//The byte layout of one kind of message's payload, i.e. everything after the type and peer id.
+//All values are little endian.
+pub trait Payload: Sized
+{
+    fn encode(&self, writer: &mut ByteWriter, direction: Direction);
+    fn decode(reader: &mut ByteReader, direction: Direction) -> Result<Self, DecodeError>;
+}
+
+pub struct ByteWriter
+{
+    bytes: Vec<u8>
+}
+
+impl ByteWriter
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            bytes: Vec::new()
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8)
+    {
+        self.bytes.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16)
+    {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32)
+    {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32)
+    {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32)
+    {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8])
+    {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8>
+    {
+        self.bytes
+    }
+}
+
+//Reads values off the front of some bytes, failing rather than reading past the end
+pub struct ByteReader<'a>
+{
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl<'a> ByteReader<'a>
+{
+    pub fn new(bytes: &'a [u8]) -> Self
+    {
+        Self
+        {
+            bytes,
+            offset: 0
+        }
+    }
+
+    pub fn remaining(&self) -> usize
+    {
+        self.bytes.len() - self.offset
+    }
+
+    pub fn take(&mut self, length: usize) -> Result<&'a [u8], DecodeError>
+    {
+        if length > self.remaining()
+        {
+            return Err(DecodeError::Truncated { needed: length, remaining: self.remaining() });
+        }
+
+        let slice = &self.bytes[self.offset..self.offset + length];
+        self.offset += length;
+
+        Ok(slice)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError>
+    {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, DecodeError>
+    {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, DecodeError>
+    {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, DecodeError>
+    {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, DecodeError>
+    {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_string(&mut self, length: usize) -> Result<String, DecodeError>
+    {
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    //For text that runs to the end of the message, like chat
+    pub fn read_remaining_string(&mut self) -> Result<String, DecodeError>
+    {
+        self.read_string(self.remaining())
+    }
+
+    //Called once everything expected has been read
+    pub fn finish(&self) -> Result<(), DecodeError>
+    {
+        match self.remaining()
+        {
+            0 => Ok(()),
+            n => Err(DecodeError::TrailingBytes(n))
+        }
+    }
+}
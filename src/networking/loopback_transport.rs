@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use crate::networking::transport::{Transport, TransportEvent, TransportFactory};
+
+#[derive(Default)]
+struct LoopbackState
+{
+    open: bool,
+    closed_by_client: bool,
+    buffered_amount: u32,
+    to_client: VecDeque<TransportEvent>,
+    from_client: Vec<Vec<u8>>,
+    endpoints: Vec<String> //Every endpoint a transport was opened for, in order
+}
+
+//An in-memory transport for running the networking code without a browser or a server.
+//The other end is a LoopbackServer, which scripts what the "server" sends and sees what the client sent.
+pub struct LoopbackTransport
+{
+    state: Rc<RefCell<LoopbackState>>
+}
+
+impl Transport for LoopbackTransport
+{
+    fn send(&mut self, bytes: &[u8]) -> bool
+    {
+        let mut state = self.state.borrow_mut();
+
+        if !state.open
+        {
+            return false;
+        }
+
+        state.from_client.push(bytes.to_vec());
+        true
+    }
+
+    fn buffered_amount(&self) -> u32
+    {
+        self.state.borrow().buffered_amount
+    }
+
+    fn poll_events(&mut self) -> Vec<TransportEvent>
+    {
+        self.state.borrow_mut().to_client.drain(..).collect()
+    }
+
+    fn close(&mut self)
+    {
+        let mut state = self.state.borrow_mut();
+
+        state.open = false;
+        state.closed_by_client = true;
+        state.to_client.clear();
+    }
+}
+
+#[derive(Clone)]
+pub struct LoopbackServer
+{
+    state: Rc<RefCell<LoopbackState>>
+}
+
+impl LoopbackServer
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            state: Rc::new(RefCell::new(LoopbackState::default()))
+        }
+    }
+
+    //Every transport from this factory talks to this server. Each new one starts a fresh, not yet open, connection.
+    pub fn factory(&self) -> TransportFactory
+    {
+        let state = self.state.clone();
+
+        Box::new(move |endpoint: &str| -> Option<Box<dyn Transport>>
+        {
+            {
+                let mut s = state.borrow_mut();
+                s.open = false;
+                s.closed_by_client = false;
+                s.to_client.clear();
+                s.endpoints.push(endpoint.to_string());
+            }
+
+            Some(Box::new(LoopbackTransport { state: state.clone() }))
+        })
+    }
+
+    pub fn accept(&self)
+    {
+        let mut state = self.state.borrow_mut();
+
+        state.open = true;
+        state.to_client.push_back(TransportEvent::Opened);
+    }
+
+    pub fn send_to_client(&self, bytes: Vec<u8>)
+    {
+        self.state.borrow_mut().to_client.push_back(TransportEvent::Message(bytes));
+    }
+
+    //Drops the connection from the server's side, as if the network went away
+    pub fn drop_connection(&self)
+    {
+        let mut state = self.state.borrow_mut();
+
+        state.open = false;
+        state.to_client.push_back(TransportEvent::Error);
+        state.to_client.push_back(TransportEvent::Closed { code: 1006, reason: String::new() });
+    }
+
+    //Pretends this many bytes are stuck waiting to be sent, as on a congested connection
+    pub fn set_buffered_amount(&self, buffered_amount: u32)
+    {
+        self.state.borrow_mut().buffered_amount = buffered_amount;
+    }
+
+    //Everything the client sent since the last call
+    pub fn take_sent(&self) -> Vec<Vec<u8>>
+    {
+        std::mem::take(&mut self.state.borrow_mut().from_client)
+    }
+
+    pub fn was_closed_by_client(&self) -> bool
+    {
+        self.state.borrow().closed_by_client
+    }
+
+    pub fn get_endpoints(&self) -> Vec<String>
+    {
+        self.state.borrow().endpoints.clone()
+    }
+}
@@ -1,12 +1,100 @@
-use std::convert::TryInto;
-use crate::util::logging::log;
+use crate::networking::codec::{ByteReader, ByteWriter, DecodeError, Direction, Payload};
+use crate::networking::peer_id::PeerId;
+use crate::graphics::animation::{AnimationState, Facing};
 
+//Bump whenever the byte layout of any message changes
+//v2: peer ids are 16 bytes rather than 36 characters of text
+pub static PROTOCOL_VERSION : u32 = 2;
+
+//Capability flags, exchanged in Hello/Welcome. Only features both sides have are used.
+pub static CAPABILITY_CHAT : u32 = 1 << 0;
+pub static CAPABILITY_RECONCILIATION : u32 = 1 << 1; //Updates carry a tick sequence number and the server sends Corrections
+pub static CAPABILITY_PING : u32 = 1 << 2; //Server answers Ping with Pong, for measuring latency
+pub static CAPABILITY_MOTION_STATE : u32 = 1 << 3; //Updates carry velocity, facing and animation state
+pub static CLIENT_CAPABILITIES : u32 = CAPABILITY_CHAT | CAPABILITY_RECONCILIATION | CAPABILITY_PING | CAPABILITY_MOTION_STATE;
+
+//Motion state flags
+pub static MOTION_FLAG_GROUNDED : u16 = 1 << 0;
+
+//Motion state: 8 (velocity) + 1 (facing) + 1 (animation state) + 2 (flags)
+static MOTION_STATE_LENGTH : usize = 12;
+
+//Protocol version + capabilities, in Hello and Welcome
+static HANDSHAKE_LENGTH : usize = 8;
+
+//Sequence number in updates to the server
+static SEQUENCE_LENGTH : usize = 4;
+
+static TEXT_PEER_ID_LENGTH : usize = 36;
+static BINARY_PEER_ID_LENGTH : usize = 16;
+
+//Protocol version from which peer ids are binary
+static BINARY_PEER_ID_PROTOCOL_VERSION : u32 = 2;
+
+//How the server writes the peer id at the start of each message. Agreed in the handshake.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PeerIdFormat
+{
+    Text,
+    Binary
+}
+
+impl PeerIdFormat
+{
+    pub fn for_protocol_version(protocol_version: u32) -> Self
+    {
+        if protocol_version >= BINARY_PEER_ID_PROTOCOL_VERSION
+        {
+            PeerIdFormat::Binary
+        } else
+        {
+            PeerIdFormat::Text
+        }
+    }
+
+    fn length(&self) -> usize
+    {
+        match self
+        {
+            PeerIdFormat::Text => TEXT_PEER_ID_LENGTH,
+            PeerIdFormat::Binary => BINARY_PEER_ID_LENGTH
+        }
+    }
+
+    fn write(&self, writer: &mut ByteWriter, peer_id: &PeerId)
+    {
+        match self
+        {
+            PeerIdFormat::Text => writer.write_bytes(peer_id.to_string().as_bytes()),
+            PeerIdFormat::Binary => writer.write_bytes(peer_id.as_bytes())
+        };
+    }
+
+    fn read(&self, reader: &mut ByteReader) -> Result<PeerId, DecodeError>
+    {
+        match self
+        {
+            PeerIdFormat::Text => PeerId::parse(&reader.read_string(self.length())?).ok_or(DecodeError::InvalidPeerId),
+            PeerIdFormat::Binary => {
+                let mut bytes = [0u8; 16];
+                bytes.copy_from_slice(reader.take(self.length())?);
+                Ok(PeerId::from_bytes(bytes))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MessageType
 {
     Update,
     Departure,
     ChatMessage,
-    Unknown
+    Hello,
+    Welcome,
+    Correction,
+    Ping,
+    Pong
 }
 
 impl MessageType
@@ -14,248 +102,651 @@ impl MessageType
     pub fn id(&self) -> i32
     {
         match self {
-            MessageType::Unknown => 0, //Or any other code
             MessageType::Update => 1,
             MessageType::Departure => 2,
-            MessageType::ChatMessage => 3
+            MessageType::ChatMessage => 3,
+            MessageType::Hello => 4,
+            MessageType::Welcome => 5,
+            MessageType::Correction => 6,
+            MessageType::Ping => 7,
+            MessageType::Pong => 8
         }
     }
 
-    pub fn from_id(id: i32) -> MessageType
+    pub fn from_id(id: i32) -> Option<MessageType>
     {
         match id {
-            1 => MessageType::Update,
-            2 => MessageType::Departure,
-            3 => MessageType::ChatMessage,
-            _ => MessageType::Unknown,
+            1 => Some(MessageType::Update),
+            2 => Some(MessageType::Departure),
+            3 => Some(MessageType::ChatMessage),
+            4 => Some(MessageType::Hello),
+            5 => Some(MessageType::Welcome),
+            6 => Some(MessageType::Correction),
+            7 => Some(MessageType::Ping),
+            8 => Some(MessageType::Pong),
+            _ => None
         }
     }
 }
 
-pub struct Message
+//How a player is moving, sent along with its position so peers can be animated the same way
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotionState
 {
-    message_type: MessageType,
-    uuid: Option<String>,
-    x: Option<f32>,
-    y: Option<f32>,
-    chat_message: Option<String>
+    velocity: glm::Vec2,
+    facing: Facing,
+    animation_state: Option<AnimationState>, //None if it's a state this client doesn't know
+    flags: u16
 }
 
-impl Message
+impl MotionState
 {
-    pub fn new_update_message(x: f32, y: f32) -> Self
+    pub fn new(velocity: glm::Vec2, animation_state: AnimationState, flags: u16) -> Self
     {
         Self
         {
-            message_type: MessageType::Update,
-            uuid: None,
-            x: Some(x),
-            y: Some(y),
-            chat_message: None
+            velocity,
+            facing: animation_state.get_facing(),
+            animation_state: Some(animation_state),
+            flags
         }
     }
 
-    pub fn new_departure_message() -> Self
+    pub fn get_velocity(&self) -> &glm::Vec2
     {
-        Self
+        &self.velocity
+    }
+
+    //Falls back to standing still facing the right way for animation states from newer clients
+    pub fn get_animation_state(&self) -> AnimationState
+    {
+        match &self.animation_state
         {
-            message_type: MessageType::Departure,
-            uuid: None,
-            x: None,
-            y: None,
-            chat_message: None
+            Some(s) => s.clone(),
+            None => AnimationState::idle(self.facing)
         }
     }
 
-    pub fn new_chat_message(chat_message: String) -> Self
+    //Whether a peer would see a difference, ignoring velocity which changes all the time
+    pub fn looks_different_from(&self, other: &MotionState) -> bool
     {
-        Self
+        self.facing != other.facing || self.animation_state != other.animation_state || self.flags != other.flags
+    }
+
+    fn write(&self, writer: &mut ByteWriter)
+    {
+        writer.write_f32(self.velocity.x);
+        writer.write_f32(self.velocity.y);
+        writer.write_u8(match self.facing { Facing::Left => 0, Facing::Right => 1 });
+        writer.write_u8(self.animation_state.as_ref().map(|s| s.id()).unwrap_or(u8::MAX));
+        writer.write_u16(self.flags);
+    }
+
+    fn read(reader: &mut ByteReader) -> Result<Self, DecodeError>
+    {
+        let velocity_x = reader.read_f32()?;
+        let velocity_y = reader.read_f32()?;
+        let facing = if reader.read_u8()? == 0 { Facing::Left } else { Facing::Right };
+        let animation_state = AnimationState::from_id(reader.read_u8()?);
+        let flags = reader.read_u16()?;
+
+        Ok(Self
         {
-            message_type: MessageType::ChatMessage,
-            uuid: None,
-            x: None,
-            y: None,
-            chat_message: Some(chat_message)
+            velocity: glm::vec2(velocity_x, velocity_y),
+            facing,
+            animation_state,
+            flags
+        })
+    }
+}
+
+//8 (x,y) + 4 (sequence, to servers that reconcile) + 12 (motion state, if the server relays it)
+#[derive(Clone, Debug, PartialEq)]
+struct UpdatePayload
+{
+    x: f32,
+    y: f32,
+    sequence: Option<u32>, //Latest predicted tick of the sender. Not relayed to other clients.
+    motion_state: Option<MotionState>
+}
+
+impl Payload for UpdatePayload
+{
+    fn encode(&self, writer: &mut ByteWriter, direction: Direction)
+    {
+        writer.write_f32(self.x);
+        writer.write_f32(self.y);
+
+        if let (Some(sequence), Direction::ToServer) = (self.sequence, direction)
+        {
+            writer.write_u32(sequence);
+        }
+
+        if let Some(motion_state) = &self.motion_state
+        {
+            motion_state.write(writer);
         }
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Option<Self>
+    fn decode(reader: &mut ByteReader, direction: Direction) -> Result<Self, DecodeError>
+    {
+        let x = reader.read_f32()?;
+        let y = reader.read_f32()?;
+
+        //The optional parts are told apart by how much is left, since both ends know which they agreed on
+        let has_sequence = direction == Direction::ToServer
+            && (reader.remaining() == SEQUENCE_LENGTH || reader.remaining() >= SEQUENCE_LENGTH + MOTION_STATE_LENGTH);
+
+        let sequence = if has_sequence { Some(reader.read_u32()?) } else { None };
+        let motion_state = if reader.remaining() > 0 { Some(MotionState::read(reader)?) } else { None };
+
+        Ok(Self { x, y, sequence, motion_state })
+    }
+}
+
+//Empty, the peer id says who left
+#[derive(Clone, Debug, PartialEq)]
+struct DeparturePayload;
+
+impl Payload for DeparturePayload
+{
+    fn encode(&self, _writer: &mut ByteWriter, _direction: Direction)
+    {
+    }
+
+    fn decode(_reader: &mut ByteReader, _direction: Direction) -> Result<Self, DecodeError>
+    {
+        Ok(Self)
+    }
+}
+
+//n (utf-8 text, to the end of the message)
+#[derive(Clone, Debug, PartialEq)]
+struct ChatPayload
+{
+    chat_message: String
+}
+
+impl Payload for ChatPayload
+{
+    fn encode(&self, writer: &mut ByteWriter, _direction: Direction)
+    {
+        writer.write_bytes(self.chat_message.as_bytes());
+    }
+
+    fn decode(reader: &mut ByteReader, _direction: Direction) -> Result<Self, DecodeError>
     {
-        let message = match Self::from_message_type_bytes(&bytes)
+        Ok(Self { chat_message: reader.read_remaining_string()? })
+    }
+}
+
+//4 (protocol version) + 4 (capabilities). Used by both Hello and Welcome.
+#[derive(Clone, Debug, PartialEq)]
+struct HandshakePayload
+{
+    protocol_version: u32,
+    capabilities: u32
+}
+
+impl Payload for HandshakePayload
+{
+    fn encode(&self, writer: &mut ByteWriter, _direction: Direction)
+    {
+        writer.write_u32(self.protocol_version);
+        writer.write_u32(self.capabilities);
+    }
+
+    fn decode(reader: &mut ByteReader, _direction: Direction) -> Result<Self, DecodeError>
+    {
+        Ok(Self
         {
-            MessageType::Update => Self::from_update_message_bytes(&bytes),
-            MessageType::Departure => Self::from_departure_message_bytes(&bytes),
-            MessageType::ChatMessage => Self::from_chat_message_bytes(&bytes),
-            MessageType::Unknown => None
-        };
+            protocol_version: reader.read_u32()?,
+            capabilities: reader.read_u32()?
+        })
+    }
+}
+
+//4 (acknowledged sequence) + 8 (x, y)
+#[derive(Clone, Debug, PartialEq)]
+struct CorrectionPayload
+{
+    acknowledged_sequence: u32, //Latest tick of ours the server has processed
+    x: f32,
+    y: f32
+}
 
-        message
+impl Payload for CorrectionPayload
+{
+    fn encode(&self, writer: &mut ByteWriter, _direction: Direction)
+    {
+        writer.write_u32(self.acknowledged_sequence);
+        writer.write_f32(self.x);
+        writer.write_f32(self.y);
     }
 
-    pub fn to_bytes(&self) -> Vec<u8>
+    fn decode(reader: &mut ByteReader, _direction: Direction) -> Result<Self, DecodeError>
     {
-        let bytes = match self.message_type
+        Ok(Self
         {
-            MessageType::Update => self.to_update_message_bytes(),
-            MessageType::Departure => self.to_departure_message_bytes(),
-            MessageType::ChatMessage => self.to_chat_message_bytes(),
-            MessageType::Unknown => Vec::<u8>::new()
-        };
-        
-        bytes
+            acknowledged_sequence: reader.read_u32()?,
+            x: reader.read_f32()?,
+            y: reader.read_f32()?
+        })
+    }
+}
+
+//4 (client clock when the Ping was sent, echoed back in the Pong)
+#[derive(Clone, Debug, PartialEq)]
+struct TimestampPayload
+{
+    timestamp: f32
+}
+
+impl Payload for TimestampPayload
+{
+    fn encode(&self, writer: &mut ByteWriter, _direction: Direction)
+    {
+        writer.write_f32(self.timestamp);
     }
 
-    fn from_message_type_bytes(bytes: &Vec<u8>) -> MessageType
+    fn decode(reader: &mut ByteReader, _direction: Direction) -> Result<Self, DecodeError>
     {
-        if bytes.len() < 4
+        Ok(Self { timestamp: reader.read_f32()? })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum MessageBody
+{
+    Update(UpdatePayload),
+    Departure(DeparturePayload),
+    ChatMessage(ChatPayload),
+    Hello(HandshakePayload),
+    Welcome(HandshakePayload),
+    Correction(CorrectionPayload),
+    Ping(TimestampPayload),
+    Pong(TimestampPayload)
+}
+
+//4 (type) + 16 or 36 (peer id, only on messages to clients) + payload
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message
+{
+    peer_id: Option<PeerId>,
+    body: MessageBody
+}
+
+impl Message
+{
+    pub fn new_update_message(x: f32, y: f32, sequence: Option<u32>, motion_state: Option<MotionState>) -> Self
+    {
+        Self
         {
-            return MessageType::Unknown;
+            peer_id: None,
+            body: MessageBody::Update(UpdatePayload { x, y, sequence, motion_state })
         }
+    }
 
-        let message_type = match &bytes[0..4].try_into()
+    pub fn new_chat_message(chat_message: String) -> Self
+    {
+        Self
         {
-            Ok(b) => {
-                MessageType::from_id(i32::from_le_bytes(*b))
-            }
-            Err(_) => {
-                MessageType::Unknown
-            }
-        };
+            peer_id: None,
+            body: MessageBody::ChatMessage(ChatPayload { chat_message })
+        }
+    }
+
+    pub fn new_hello_message() -> Self
+    {
+        Self
+        {
+            peer_id: None,
+            body: MessageBody::Hello(HandshakePayload { protocol_version: PROTOCOL_VERSION, capabilities: CLIENT_CAPABILITIES })
+        }
+    }
 
-        message_type
+    pub fn new_ping_message(timestamp: f32) -> Self
+    {
+        Self
+        {
+            peer_id: None,
+            body: MessageBody::Ping(TimestampPayload { timestamp })
+        }
     }
 
-    fn to_update_message_bytes(&self) -> Vec<u8>
+    //Decodes a message received from the server
+    pub fn from_bytes(bytes: &[u8], peer_id_format: PeerIdFormat) -> Result<Self, DecodeError>
     {
-        //Outgoing Update: 4 (type) + 8 (x,y)
-        let mut bytes = Vec::<u8>::new();
+        Self::decode(bytes, Direction::ToClient, peer_id_format)
+    }
 
-        bytes.extend_from_slice(&self.message_type.id().to_le_bytes()); //4
+    //Encodes a message to send to the server
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        self.encode(Direction::ToServer, None)
+    }
 
-        if self.x.is_none() || self.y.is_none()
+    //Encodes a message the way the server sends it, prefixed with the id of the client it's about.
+    //None if it isn't about anyone, as there is no id to write. Only the server sends these, so this is for tests.
+    #[cfg(test)]
+    pub fn to_client_bytes(&self, peer_id_format: PeerIdFormat) -> Option<Vec<u8>>
+    {
+        let peer_id = self.peer_id?;
+        Some(self.encode(Direction::ToClient, Some((&peer_id, peer_id_format))))
+    }
+
+    fn encode(&self, direction: Direction, peer_id: Option<(&PeerId, PeerIdFormat)>) -> Vec<u8>
+    {
+        let mut writer = ByteWriter::new();
+
+        writer.write_i32(self.message_type().id());
+
+        if let Some((peer_id, peer_id_format)) = peer_id
         {
-            return bytes;
+            peer_id_format.write(&mut writer, peer_id);
         }
 
-        bytes.extend_from_slice(&self.x.unwrap().to_le_bytes()); //4
-        bytes.extend_from_slice(&self.y.unwrap().to_le_bytes()); //4
+        match &self.body
+        {
+            MessageBody::Update(p) => p.encode(&mut writer, direction),
+            MessageBody::Departure(p) => p.encode(&mut writer, direction),
+            MessageBody::ChatMessage(p) => p.encode(&mut writer, direction),
+            MessageBody::Hello(p) | MessageBody::Welcome(p) => p.encode(&mut writer, direction),
+            MessageBody::Correction(p) => p.encode(&mut writer, direction),
+            MessageBody::Ping(p) | MessageBody::Pong(p) => p.encode(&mut writer, direction)
+        };
 
-        bytes
+        writer.into_bytes()
     }
 
-    fn from_update_message_bytes(bytes: &Vec<u8>) -> Option<Self>
+    pub fn decode(bytes: &[u8], direction: Direction, peer_id_format: PeerIdFormat) -> Result<Self, DecodeError>
     {
-        //Incoming Update: 4 (type) + 36 (uuid) + 8 (x, y)
+        let mut reader = ByteReader::new(bytes);
 
-        if bytes.len() < 48
+        let type_id = reader.read_i32()?;
+
+        let message_type = match MessageType::from_id(type_id)
         {
-            return None;
-        }
+            Some(t) => t,
+            None => { return Err(DecodeError::UnknownMessageType(type_id)); }
+        };
 
-        let x = match &bytes[40..44].try_into()
+        //The Welcome is what says which format the server uses, so it's read in whichever it is.
+        //It's a fixed length, so the peer id is whatever is left over.
+        let peer_id_format = match message_type
         {
-            Ok(b) => f32::from_le_bytes(*b),
-            Err(_) => 0.0
+            MessageType::Welcome if reader.remaining() == BINARY_PEER_ID_LENGTH + HANDSHAKE_LENGTH => PeerIdFormat::Binary,
+            MessageType::Welcome => PeerIdFormat::Text,
+            _ => peer_id_format
         };
 
-        let y = match &bytes[44..48].try_into()
+        let peer_id = match direction
         {
-            Ok(b) => f32::from_le_bytes(*b),
-            Err(_) => 0.0
+            Direction::ToClient => Some(peer_id_format.read(&mut reader)?),
+            Direction::ToServer => None
         };
 
-        Some(Self
+        let body = match message_type
         {
-            message_type: MessageType::Update,
-            uuid: Some(String::from_utf8_lossy(&bytes[4..40]).to_string()),
-            x: Some(x),
-            y: Some(y),
-            chat_message: None
-        })
+            MessageType::Update => MessageBody::Update(UpdatePayload::decode(&mut reader, direction)?),
+            MessageType::Departure => MessageBody::Departure(DeparturePayload::decode(&mut reader, direction)?),
+            MessageType::ChatMessage => MessageBody::ChatMessage(ChatPayload::decode(&mut reader, direction)?),
+            MessageType::Hello => MessageBody::Hello(HandshakePayload::decode(&mut reader, direction)?),
+            MessageType::Welcome => MessageBody::Welcome(HandshakePayload::decode(&mut reader, direction)?),
+            MessageType::Correction => MessageBody::Correction(CorrectionPayload::decode(&mut reader, direction)?),
+            MessageType::Ping => MessageBody::Ping(TimestampPayload::decode(&mut reader, direction)?),
+            MessageType::Pong => MessageBody::Pong(TimestampPayload::decode(&mut reader, direction)?)
+        };
+
+        reader.finish()?;
+
+        Ok(Self { peer_id, body })
     }
 
-    fn to_departure_message_bytes(&self) -> Vec<u8>
+    pub fn message_type(&self) -> MessageType
     {
-        //NB This will never actually be sent - server generates these
-        //Outgoing Departure: 4 (type)
-        let mut bytes = Vec::<u8>::new();
-        bytes.extend_from_slice(&self.message_type.id().to_le_bytes()); //4
-        bytes
+        match &self.body
+        {
+            MessageBody::Update(_) => MessageType::Update,
+            MessageBody::Departure(_) => MessageType::Departure,
+            MessageBody::ChatMessage(_) => MessageType::ChatMessage,
+            MessageBody::Hello(_) => MessageType::Hello,
+            MessageBody::Welcome(_) => MessageType::Welcome,
+            MessageBody::Correction(_) => MessageType::Correction,
+            MessageBody::Ping(_) => MessageType::Ping,
+            MessageBody::Pong(_) => MessageType::Pong
+        }
     }
 
-    fn from_departure_message_bytes(bytes: &Vec<u8>) -> Option<Self>
+    pub fn peer_id(&self) -> Option<PeerId>
     {
-        //Incoming Departure: 4 (type) + 36 (uuid)
-        if bytes.len() < 40
+        self.peer_id
+    }
+
+    pub fn x(&self) -> Option<f32>
+    {
+        match &self.body
         {
-            return None;
+            MessageBody::Update(p) => Some(p.x),
+            MessageBody::Correction(p) => Some(p.x),
+            _ => None
         }
+    }
 
-        Some(Self
+    pub fn y(&self) -> Option<f32>
+    {
+        match &self.body
         {
-            message_type: MessageType::Departure,
-            uuid: Some(String::from_utf8_lossy(&bytes[4..40]).to_string()),
-            x: None,
-            y: None,
-            chat_message: None
-        })
+            MessageBody::Update(p) => Some(p.y),
+            MessageBody::Correction(p) => Some(p.y),
+            _ => None
+        }
     }
 
-    fn to_chat_message_bytes(&self) -> Vec<u8>
+    pub fn chat_message(&self) -> Option<&String>
     {
-        //Outgoing Chat: 4 (type) + n (message)
-        let mut bytes = Vec::<u8>::new();
-        bytes.extend_from_slice(&self.message_type.id().to_le_bytes()); //4
+        match &self.body
+        {
+            MessageBody::ChatMessage(p) => Some(&p.chat_message),
+            _ => None
+        }
+    }
 
-        if self.chat_message.is_none()
+    pub fn protocol_version(&self) -> Option<u32>
+    {
+        match &self.body
         {
-            return bytes;
+            MessageBody::Hello(p) | MessageBody::Welcome(p) => Some(p.protocol_version),
+            _ => None
         }
+    }
 
-        bytes.extend_from_slice(&self.chat_message.as_ref().unwrap().as_bytes());
-        bytes
+    pub fn capabilities(&self) -> Option<u32>
+    {
+        match &self.body
+        {
+            MessageBody::Hello(p) | MessageBody::Welcome(p) => Some(p.capabilities),
+            _ => None
+        }
     }
 
-    fn from_chat_message_bytes(bytes: &Vec<u8>) -> Option<Self>
+    pub fn acknowledged_sequence(&self) -> Option<u32>
     {
-        //Incoming Chat: 4 (type) + 36 (uuid) + n (message)
-        if bytes.len() < 41
+        match &self.body
         {
-            return None;
+            MessageBody::Correction(p) => Some(p.acknowledged_sequence),
+            _ => None
         }
+    }
 
-        Some(Self
+    pub fn timestamp(&self) -> Option<f32>
+    {
+        match &self.body
         {
-            message_type: MessageType::ChatMessage,
-            uuid: Some(String::from_utf8_lossy(&bytes[4..40]).to_string()),
-            x: None,
-            y: None,
-            chat_message: Some(String::from_utf8_lossy(&bytes[40..]).to_string())
-        })
+            MessageBody::Ping(p) | MessageBody::Pong(p) => Some(p.timestamp),
+            _ => None
+        }
     }
 
-    pub fn message_type(&self) -> &MessageType
+    pub fn motion_state(&self) -> Option<&MotionState>
     {
-        &&self.message_type
+        match &self.body
+        {
+            MessageBody::Update(p) => p.motion_state.as_ref(),
+            _ => None
+        }
     }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    static UUID : &str = "0123abcd-4567-89ef-0123-456789abcdef";
 
-    pub fn uuid(&self) -> &Option<String>
+    fn peer_id() -> PeerId
     {
-        &&self.uuid
+        PeerId::parse(UUID).unwrap()
     }
 
-    pub fn x(&self) -> &Option<f32>
+    //One of every message type, as they'd be received from the server
+    fn every_message() -> Vec<Message>
     {
-        &&self.x
+        let motion_state = MotionState::new(glm::vec2(-1.5,0.0), AnimationState::WalkingLeft, MOTION_FLAG_GROUNDED);
+
+        let bodies = vec![
+            MessageBody::Update(UpdatePayload { x: 1.0, y: -2.5, sequence: None, motion_state: None }),
+            MessageBody::Update(UpdatePayload { x: 1.0, y: -2.5, sequence: None, motion_state: Some(motion_state) }),
+            MessageBody::Departure(DeparturePayload),
+            MessageBody::ChatMessage(ChatPayload { chat_message: "hello possums ü".to_string() }),
+            MessageBody::Hello(HandshakePayload { protocol_version: PROTOCOL_VERSION, capabilities: CLIENT_CAPABILITIES }),
+            MessageBody::Welcome(HandshakePayload { protocol_version: 1, capabilities: CAPABILITY_CHAT }),
+            MessageBody::Correction(CorrectionPayload { acknowledged_sequence: 42, x: 3.0, y: 4.0 }),
+            MessageBody::Ping(TimestampPayload { timestamp: 1234.5 }),
+            MessageBody::Pong(TimestampPayload { timestamp: 1234.5 })
+        ];
+
+        bodies.into_iter().map(|body| Message { peer_id: Some(peer_id()), body }).collect()
     }
 
-    pub fn y(&self) -> &Option<f32>
+    #[test]
+    fn every_message_type_round_trips_both_ways()
     {
-        &&self.y
+        for format in [PeerIdFormat::Text, PeerIdFormat::Binary]
+        {
+            for message in every_message()
+            {
+                assert_eq!(Message::decode(&message.to_client_bytes(format).unwrap(), Direction::ToClient, format), Ok(message.clone()));
+
+                //Messages to the server don't carry a peer id, and there's nothing to write for one to a client
+                let to_server = Message { peer_id: None, body: message.body.clone() };
+                assert_eq!(Message::decode(&to_server.to_bytes(), Direction::ToServer, format), Ok(to_server.clone()));
+                assert_eq!(to_server.to_client_bytes(format), None);
+            }
+        }
     }
 
-    pub fn chat_message(&self) -> &Option<String>
+    #[test]
+    fn update_sequence_and_motion_state_round_trip_in_any_combination()
     {
-        &&self.chat_message
+        let motion_state = MotionState::new(glm::vec2(2.0,0.0), AnimationState::WalkingRight, 0);
+
+        for sequence in [None, Some(7)]
+        {
+            for motion_state in [None, Some(motion_state.clone())]
+            {
+                let message = Message::new_update_message(10.0, 20.0, sequence, motion_state);
+                assert_eq!(Message::decode(&message.to_bytes(), Direction::ToServer, PeerIdFormat::Binary), Ok(message));
+            }
+        }
+    }
+
+    #[test]
+    fn layout_matches_the_protocol()
+    {
+        let bytes = Message::new_update_message(1.0, 2.0, Some(3), None).to_bytes();
+        assert_eq!(bytes, [&1i32.to_le_bytes()[..], &1.0f32.to_le_bytes(), &2.0f32.to_le_bytes(), &3u32.to_le_bytes()].concat());
+
+        let bytes = Message::new_hello_message().to_bytes();
+        assert_eq!(bytes, [&4i32.to_le_bytes()[..], &PROTOCOL_VERSION.to_le_bytes(), &CLIENT_CAPABILITIES.to_le_bytes()].concat());
+
+        //Welcomes are read the same whatever format was expected, older servers send the id as text
+        for format in [PeerIdFormat::Text, PeerIdFormat::Binary]
+        {
+            let bytes = [&5i32.to_le_bytes()[..], UUID.as_bytes(), &1u32.to_le_bytes(), &3u32.to_le_bytes()].concat();
+            let welcome = Message::from_bytes(&bytes, format).unwrap();
+            assert_eq!(welcome.peer_id(), Some(peer_id()));
+            assert_eq!(welcome.protocol_version(), Some(1));
+            assert_eq!(welcome.capabilities(), Some(3));
+
+            let bytes = [&5i32.to_le_bytes()[..], &peer_id().as_bytes()[..], &2u32.to_le_bytes(), &3u32.to_le_bytes()].concat();
+            let welcome = Message::from_bytes(&bytes, format).unwrap();
+            assert_eq!(welcome.peer_id(), Some(peer_id()));
+            assert_eq!(welcome.protocol_version(), Some(2));
+        }
+
+        let departure = Message { peer_id: Some(peer_id()), body: MessageBody::Departure(DeparturePayload) };
+        assert_eq!(departure.to_client_bytes(PeerIdFormat::Text).unwrap(), [&2i32.to_le_bytes()[..], UUID.as_bytes()].concat());
+        assert_eq!(departure.to_client_bytes(PeerIdFormat::Binary).unwrap(), [&2i32.to_le_bytes()[..], &[0x01, 0x23, 0xab, 0xcd, 0x45, 0x67, 0x89, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]].concat());
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected()
+    {
+        for format in [PeerIdFormat::Text, PeerIdFormat::Binary]
+        {
+            for message in every_message()
+            {
+                let bytes = message.to_client_bytes(format).unwrap();
+
+                //Some shorter lengths are complete messages in their own right
+                let is_complete = |length: usize| match message.message_type()
+                {
+                    MessageType::ChatMessage => length >= 4 + format.length(),
+                    MessageType::Update => length == 4 + format.length() + 8,
+                    //A text Welcome cut short by 20 bytes is the length of a binary one
+                    MessageType::Welcome => length == 4 + BINARY_PEER_ID_LENGTH + HANDSHAKE_LENGTH,
+                    _ => false
+                };
+
+                for length in (0..bytes.len()).filter(|length| !is_complete(*length))
+                {
+                    assert!(Message::from_bytes(&bytes[..length], format).is_err(), "{:?} cut to {} bytes was accepted", message.message_type(), length);
+                }
+            }
+        }
+
+        assert_eq!(Message::from_bytes(&[1, 0], PeerIdFormat::Binary), Err(DecodeError::Truncated { needed: 4, remaining: 2 }));
+    }
+
+    #[test]
+    fn garbage_is_rejected()
+    {
+        assert_eq!(Message::from_bytes(&[], PeerIdFormat::Binary), Err(DecodeError::Truncated { needed: 4, remaining: 0 }));
+        assert_eq!(Message::from_bytes(&99i32.to_le_bytes(), PeerIdFormat::Binary), Err(DecodeError::UnknownMessageType(99)));
+        assert_eq!(Message::from_bytes(&0i32.to_le_bytes(), PeerIdFormat::Binary), Err(DecodeError::UnknownMessageType(0)));
+
+        let invalid_text_id = [&2i32.to_le_bytes()[..], &[0xff; 36]].concat();
+        assert_eq!(Message::from_bytes(&invalid_text_id, PeerIdFormat::Text), Err(DecodeError::InvalidUtf8));
+
+        let not_a_uuid = [&2i32.to_le_bytes()[..], "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaz".as_bytes()].concat();
+        assert_eq!(Message::from_bytes(&not_a_uuid, PeerIdFormat::Text), Err(DecodeError::InvalidPeerId));
+
+        let invalid_chat = [&3i32.to_le_bytes()[..], &peer_id().as_bytes()[..], &[0xc3, 0x28]].concat();
+        assert_eq!(Message::from_bytes(&invalid_chat, PeerIdFormat::Binary), Err(DecodeError::InvalidUtf8));
+
+        let padded_welcome = [&5i32.to_le_bytes()[..], UUID.as_bytes(), &[0; 12]].concat();
+        assert_eq!(Message::from_bytes(&padded_welcome, PeerIdFormat::Binary), Err(DecodeError::TrailingBytes(4)));
+
+        //Any type id followed by junk is either decoded or rejected, never a panic
+        for format in [PeerIdFormat::Text, PeerIdFormat::Binary]
+        {
+            for type_id in 0..=255u8
+            {
+                let bytes = [&[type_id, 0, 0, 0][..], UUID.as_bytes(), &[0x7f; 5]].concat();
+                let _ = Message::from_bytes(&bytes, format);
+            }
+        }
     }
 }
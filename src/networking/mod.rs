@@ -1,2 +1,8 @@
 pub mod server_connection;
-pub mod message;
\ No newline at end of file
+pub mod message;
+pub mod codec;
+pub mod peer_id;
+pub mod transport;
+pub mod websocket_transport;
+#[cfg(test)]
+pub mod loopback_transport;
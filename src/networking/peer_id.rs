@@ -0,0 +1,82 @@
+use std::fmt;
+
+//A client's id on the server, a 16 byte uuid. Servers from protocol v2 send it as those 16 bytes,
+//older ones as 36 characters of text ("xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx"). Either way it ends up as one of these.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PeerId
+{
+    bytes: [u8; 16]
+}
+
+impl PeerId
+{
+    pub fn from_bytes(bytes: [u8; 16]) -> Self
+    {
+        Self { bytes }
+    }
+
+    //The textual form. None if it isn't a hyphenated uuid.
+    pub fn parse(text: &str) -> Option<Self>
+    {
+        if text.len() != 36
+        {
+            return None;
+        }
+
+        let mut digits = Vec::<u8>::with_capacity(32);
+
+        for (i, c) in text.chars().enumerate()
+        {
+            if matches!(i, 8 | 13 | 18 | 23)
+            {
+                if c != '-'
+                {
+                    return None;
+                }
+
+                continue;
+            }
+
+            digits.push(c.to_digit(16)? as u8);
+        }
+
+        //Fewer if there were multi-byte characters
+        if digits.len() != 32
+        {
+            return None;
+        }
+
+        let mut bytes = [0u8; 16];
+
+        for (i, byte) in bytes.iter_mut().enumerate()
+        {
+            *byte = digits[i * 2] * 16 + digits[i * 2 + 1];
+        }
+
+        Some(Self { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16]
+    {
+        &self.bytes
+    }
+}
+
+//Hyphenated lowercase hex, the same as the textual form
+impl fmt::Display for PeerId
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        for (i, byte) in self.bytes.iter().enumerate()
+        {
+            if matches!(i, 4 | 6 | 8 | 10)
+            {
+                write!(f, "-")?;
+            }
+
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
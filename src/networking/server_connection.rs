@@ -1,132 +1,594 @@
-use web_sys::WebSocket;
-use web_sys::MessageEvent;
-use wasm_bindgen::closure::Closure;
-use wasm_bindgen::JsCast;
-use web_sys::js_sys::{ArrayBuffer,Uint8Array};
-use web_sys::BinaryType;
-use std::sync::Arc;
-use std::sync::Mutex;
-use crate::networking::message::Message;
+use crate::networking::transport::{Transport, TransportEvent, TransportFactory};
+use crate::networking::websocket_transport::WebSocketTransport;
+use crate::networking::message::{Message, MessageType, MotionState, PeerIdFormat, PROTOCOL_VERSION, CLIENT_CAPABILITIES, CAPABILITY_CHAT, CAPABILITY_PING};
+use crate::networking::peer_id::PeerId;
 use crate::util::logging::log;
 
-static RATE_LIMIT : f32 = 50.0;
+//Updates are sent at most this often, in milliseconds. Slow or congested connections get fewer.
+static MIN_UPDATE_INTERVAL : f32 = 50.0;
+pub static MAX_UPDATE_INTERVAL : f32 = 400.0;
+
+//An unchanged position is still sent this often, so the server knows we're there
+static KEEPALIVE_INTERVAL : f32 = 1000.0;
+
+//How far the player has to move since the last update for it to count as a change
+static UPDATE_POSITION_THRESHOLD : f32 = 0.5;
+
+//More unsent bytes than this and the connection is falling behind what we send it
+static CONGESTED_BUFFERED_AMOUNT : u32 = 4096;
+
+static PING_INTERVAL : f32 = 2000.0;
+
+//Weight of each new round trip measurement in the smoothed round trip time
+static ROUND_TRIP_SMOOTHING : f32 = 0.125;
+
+//Servers from before the handshake never answer Hello, so after this long assume one of those
+static WELCOME_TIMEOUT : f32 = 3000.0;
+
+//Oldest server protocol this client can still talk to
+static MIN_SUPPORTED_PROTOCOL_VERSION : u32 = 1;
+
+//The least far behind real time remote peers are shown, in milliseconds. Each is shown further behind
+//if its updates arrive further apart (see PeerInterpolation), as they do from slow or congested senders.
+static DEFAULT_INTERPOLATION_DELAY : f32 = 100.0;
+
+//Reconnect delays double after every failed attempt, up to the max. In milliseconds.
+static INITIAL_RECONNECT_DELAY : f32 = 1000.0;
+static MAX_RECONNECT_DELAY : f32 = 30000.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConnectionState
+{
+    Offline, //Not trying to connect to anything
+    Connecting,
+    Open,
+    Closed, //Won't reconnect by itself, e.g. the server was refused
+    Backoff { retry_in: f32 } //Lost the connection, trying again after a delay
+}
+
+impl ConnectionState
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            ConnectionState::Offline => "Offline",
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Open => "Open",
+            ConnectionState::Closed => "Closed",
+            ConnectionState::Backoff { .. } => "Backoff"
+        }
+    }
+}
+
+enum Handshake
+{
+    NotStarted,
+    AwaitingWelcome { time_waited: f32 },
+    Established { protocol_version: u32, capabilities: u32 },
+    Legacy, //Server doesn't do the handshake - only the original update/departure/chat messages are used
+    Refused { reason: String }
+}
 
 pub struct ServerConnection
 {
-    socket: Option<WebSocket>,
+    transport: Option<Box<dyn Transport>>,
+    transport_factory: TransportFactory,
+    endpoint: Option<String>,
+    state: ConnectionState,
+    reconnect_delay: f32,
     time_since_last_update: f32,
-    inbound_message_queue: Arc<Mutex<Vec<Message>>>
+    update_interval: f32,
+    last_sent_position: Option<glm::Vec2>,
+    last_sent_motion_state: Option<MotionState>,
+    time_since_last_ping: f32,
+    round_trip_time: Option<f32>, //Smoothed, in milliseconds
+    inbound_message_queue: Vec<Message>,
+    handshake: Handshake,
+    client_id: Option<PeerId>, //Assigned by the server in its Welcome
+    session_ended: bool,
+    clock: f32, //Milliseconds of updates so far, used to timestamp what arrives
+    interpolation_delay: f32
 }
 
 impl ServerConnection
 {
+    //Starts offline, call connect to join a server
     pub fn new() -> Self
     {
-        let inbound_message_queue = Arc::new(Mutex::new(Vec::<Message>::new()));
+        Self::new_with_transport_factory(WebSocketTransport::factory())
+    }
 
-        let remote = "wss://backend.poss.ly/";
-        //let local = "ws://127.0.0.1:8000";
+    //Connections are opened with the given factory instead of as WebSockets, e.g. to run without a browser
+    pub fn new_with_transport_factory(transport_factory: TransportFactory) -> Self
+    {
+        Self
+        {
+            transport: None,
+            transport_factory,
+            endpoint: None,
+            state: ConnectionState::Offline,
+            reconnect_delay: INITIAL_RECONNECT_DELAY,
+            inbound_message_queue: Vec::new(),
+            time_since_last_update: 0.0,
+            update_interval: MIN_UPDATE_INTERVAL,
+            last_sent_position: None,
+            last_sent_motion_state: None,
+            time_since_last_ping: 0.0,
+            round_trip_time: None,
+            handshake: Handshake::NotStarted,
+            client_id: None,
+            session_ended: false,
+            clock: 0.0,
+            interpolation_delay: DEFAULT_INTERPOLATION_DELAY
+        }
+    }
 
-        let socket = match WebSocket::new(remote)
+    //Connects to the given ws:// or wss:// endpoint, leaving whatever server we were on before.
+    //Keeps reconnecting to it if the connection drops, until disconnect is called.
+    pub fn connect(&mut self, endpoint: &str)
+    {
+        self.disconnect();
+
+        self.endpoint = Some(endpoint.to_string());
+        self.reconnect_delay = INITIAL_RECONNECT_DELAY;
+        self.open_transport();
+    }
+
+    //Leaves the current server (if any) and goes offline
+    pub fn disconnect(&mut self)
+    {
+        self.close_transport();
+
+        self.endpoint = None;
+        self.state = ConnectionState::Offline;
+    }
+
+    fn open_transport(&mut self)
+    {
+        let endpoint = match self.endpoint.clone()
         {
-            Ok(ws) => {
+            Some(e) => e,
+            None => { return; }
+        };
 
-                log(&format!("WebSocket connection established to {}",remote));
+        log(&format!("Connecting to {}",endpoint));
 
-                let queue_ref = inbound_message_queue.clone();
+        self.transport = (self.transport_factory)(&endpoint);
 
-                ws.set_binary_type(BinaryType::Arraybuffer);
-                let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
-                    if let Ok(abuf) = e.data().dyn_into::<ArrayBuffer>() {
-                        //TODO unnecessary copy?
-                        match Message::from_bytes(Uint8Array::new(&abuf).to_vec())
-                        {
-                            Some(m) => {
-                                queue_ref.lock().unwrap().push(m);
-                            },
-                            None => {
-                                log(&format!("Failed to receive a message - not decoded properly"));
-                            } 
-                        };
-                    } else
-                    {
-                        log(&format!("Failed convert inbound message data to arraybuffer"));
-                    }
-                });
-                ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-                onmessage_callback.forget();
-                Some(ws)
-            },
-            Err(_) => 
+        //Most likely a malformed endpoint, which retrying won't fix
+        self.state = match self.transport
+        {
+            Some(_) => ConnectionState::Connecting,
+            None => ConnectionState::Closed
+        };
+    }
+
+    //Closes the transport without triggering a reconnect, and forgets everything about the session on it
+    fn close_transport(&mut self)
+    {
+        if let Some(mut transport) = self.transport.take()
+        {
+            transport.close();
+        }
+
+        self.end_session();
+    }
+
+    fn end_session(&mut self)
+    {
+        if !matches!(self.handshake, Handshake::NotStarted) || self.client_id.is_some()
+        {
+            self.session_ended = true;
+        }
+
+        self.handshake = Handshake::NotStarted;
+        self.client_id = None;
+        self.time_since_last_update = 0.0;
+        self.update_interval = MIN_UPDATE_INTERVAL;
+        self.last_sent_position = None;
+        self.last_sent_motion_state = None;
+        self.time_since_last_ping = 0.0;
+        self.round_trip_time = None;
+        self.inbound_message_queue.clear();
+    }
+
+    pub fn get_endpoint(&self) -> &Option<String>
+    {
+        &self.endpoint
+    }
+
+    pub fn get_state(&self) -> ConnectionState
+    {
+        self.state
+    }
+
+    //True once after leaving a server, so peers from it can be cleaned up
+    pub fn take_session_ended(&mut self) -> bool
+    {
+        std::mem::replace(&mut self.session_ended, false)
+    }
+
+    //Called once per tick. Follows the socket's lifecycle, handles the handshake and schedules reconnects.
+    pub fn update(&mut self, delta_time: f32)
+    {
+        self.clock += delta_time;
+
+        if let ConnectionState::Backoff { retry_in } = &mut self.state
+        {
+            *retry_in -= delta_time;
+
+            if *retry_in <= 0.0
             {
-                log(&format!("Failed to open WebSocket connection"));
-                None
+                self.open_transport();
             }
+
+            return;
+        }
+
+        let events = match self.transport.as_mut()
+        {
+            Some(t) => t.poll_events(),
+            None => Vec::new()
         };
 
-        Self
+        for event in events
         {
-            socket,
-            inbound_message_queue: inbound_message_queue,
-            time_since_last_update: RATE_LIMIT
+            match event
+            {
+                TransportEvent::Opened => {
+                    log(&format!("Connected to {}", self.endpoint.as_deref().unwrap_or("server")));
+                    self.state = ConnectionState::Open;
+                },
+                TransportEvent::Message(bytes) => {
+                    match Message::from_bytes(&bytes, self.get_peer_id_format())
+                    {
+                        //Handled straight away, since it decides how the messages after it are decoded
+                        Ok(m) if m.message_type() == MessageType::Welcome => {
+                            self.process_welcome(&m);
+
+                            if self.state != ConnectionState::Open
+                            {
+                                break;
+                            }
+                        },
+                        Ok(m) => {
+                            self.inbound_message_queue.push(m);
+                        },
+                        Err(e) => {
+                            log(&format!("Failed to decode a message: {}", e));
+                        } 
+                    };
+                },
+                TransportEvent::Closed { code, reason } => {
+                    log(&format!("Connection closed ({}) {}", code, reason));
+                    self.connection_lost();
+                    break;
+                },
+                TransportEvent::Error => {
+                    //Always followed by a close, which is where reconnecting is handled
+                    log("Connection error");
+                }
+            };
+        }
+
+        if self.state != ConnectionState::Open
+        {
+            return;
         }
+
+        match &mut self.handshake
+        {
+            Handshake::NotStarted => {
+                self.handshake = Handshake::AwaitingWelcome { time_waited: 0.0 };
+                self.send_bytes(&Message::new_hello_message().to_bytes());
+            },
+            Handshake::AwaitingWelcome { time_waited } => {
+                *time_waited += delta_time;
+
+                if *time_waited >= WELCOME_TIMEOUT
+                {
+                    log("Server did not answer the handshake, assuming it predates it");
+                    self.handshake = Handshake::Legacy;
+                    self.reconnect_delay = INITIAL_RECONNECT_DELAY;
+                }
+            },
+            Handshake::Established { .. } => {
+                self.update_ping(delta_time);
+            },
+            _ => {}
+        };
     }
 
-    pub fn send_message_if_ready(&mut self, message: &Message, delta_time: f32)
+    fn update_ping(&mut self, delta_time: f32)
     {
-        self.time_since_last_update += delta_time;
+        if !self.has_capability(CAPABILITY_PING)
+        {
+            return;
+        }
 
-        if self.time_since_last_update < RATE_LIMIT
+        self.time_since_last_ping += delta_time;
+
+        if self.time_since_last_ping < PING_INTERVAL
         {
             return;
         }
 
-        self.time_since_last_update = 0.0;
-        self.immediately_send_message(message);
+        self.time_since_last_ping = 0.0;
+        self.send_bytes(&Message::new_ping_message(self.clock).to_bytes());
     }
 
-    pub fn immediately_send_message(&mut self, message: &Message)
+    fn process_pong(&mut self, message: &Message)
     {
-        if self.socket.is_none()
+        let sent_at = match message.timestamp()
         {
+            Some(t) => t,
+            None => { return; }
+        };
+
+        let sample = (self.clock - sent_at).max(0.0);
+
+        self.round_trip_time = match self.round_trip_time
+        {
+            Some(rtt) => Some(rtt + (sample - rtt) * ROUND_TRIP_SMOOTHING),
+            None => Some(sample)
+        };
+    }
+
+    //None until a server that answers pings has answered one
+    pub fn get_round_trip_time(&self) -> Option<f32>
+    {
+        self.round_trip_time
+    }
+
+    #[cfg(test)]
+    pub fn get_update_interval(&self) -> f32
+    {
+        self.update_interval
+    }
+
+    //The socket closed without us asking it to. Try again later, waiting longer each time it keeps failing.
+    fn connection_lost(&mut self)
+    {
+        self.transport = None;
+        self.end_session();
+
+        if self.endpoint.is_none()
+        {
+            self.state = ConnectionState::Offline;
             return;
         }
 
-        let socket = self.socket.as_ref().unwrap();
+        log(&format!("Reconnecting in {} seconds", self.reconnect_delay / 1000.0));
 
-        if socket.ready_state() != 1
+        self.state = ConnectionState::Backoff { retry_in: self.reconnect_delay };
+        self.reconnect_delay = (self.reconnect_delay * 2.0).min(MAX_RECONNECT_DELAY);
+    }
+
+    fn process_welcome(&mut self, message: &Message)
+    {
+        if !matches!(self.handshake, Handshake::AwaitingWelcome { .. })
         {
             return;
         }
 
-        let message_byte_array = message.to_bytes();
+        let protocol_version = message.protocol_version().unwrap_or(0);
+        let capabilities = message.capabilities().unwrap_or(0) & CLIENT_CAPABILITIES;
 
-        let buffer = ArrayBuffer::new(message_byte_array.len() as u32);
-        let byte_array = Uint8Array::new(&buffer);
+        if protocol_version < MIN_SUPPORTED_PROTOCOL_VERSION
+        {
+            self.refuse(format!("Server protocol v{} is too old for this client (needs v{} or newer)", protocol_version, MIN_SUPPORTED_PROTOCOL_VERSION));
+            return;
+        }
 
-        for i in 0..message_byte_array.len()
+        if protocol_version > PROTOCOL_VERSION
         {
-            //TODO: make this better
-            //Remove/make casts safer, don't iterate
-            byte_array.fill(message_byte_array[i],i as u32,(i+1) as u32); 
+            self.refuse(format!("Server protocol v{} is newer than this client (v{}) - try reloading the page", protocol_version, PROTOCOL_VERSION));
+            return;
         }
 
-        match socket.send_with_array_buffer(&buffer)
+        log(&format!("Handshake complete: protocol v{}, capabilities {:#x}", protocol_version, capabilities));
+        self.client_id = message.peer_id();
+        self.handshake = Handshake::Established { protocol_version, capabilities };
+        self.reconnect_delay = INITIAL_RECONNECT_DELAY;
+    }
+
+    //Stops talking to a server we can't understand, rather than sending it bytes it would misread.
+    //Reconnecting wouldn't help, so this stays closed.
+    fn refuse(&mut self, reason: String)
+    {
+        log(&reason);
+
+        self.close_transport();
+
+        self.state = ConnectionState::Closed;
+        self.handshake = Handshake::Refused { reason };
+    }
+
+    pub fn get_time(&self) -> f32
+    {
+        self.clock
+    }
+
+    pub fn get_interpolation_delay(&self) -> f32
+    {
+        self.interpolation_delay
+    }
+
+    pub fn set_interpolation_delay(&mut self, interpolation_delay: f32)
+    {
+        self.interpolation_delay = interpolation_delay.max(0.0);
+    }
+
+    pub fn get_client_id(&self) -> &Option<PeerId>
+    {
+        &self.client_id
+    }
+
+    //Servers before protocol v2, and ones that skip the handshake, send peer ids as text
+    fn get_peer_id_format(&self) -> PeerIdFormat
+    {
+        match self.handshake
+        {
+            Handshake::Established { protocol_version, capabilities: _ } => PeerIdFormat::for_protocol_version(protocol_version),
+            _ => PeerIdFormat::Text
+        }
+    }
+
+    pub fn has_capability(&self, capability: u32) -> bool
+    {
+        match self.handshake
         {
-            Ok(_) => {},
-            Err(err) => log(format!("error sending message: {:?}", err).as_str()),
+            Handshake::Established { protocol_version: _, capabilities } => capabilities & capability != 0,
+            Handshake::Legacy => capability == CAPABILITY_CHAT, //Chat was part of the original protocol
+            _ => false
+        }
+    }
+
+    //Human readable, for showing on the page
+    pub fn get_status(&self) -> String
+    {
+        let endpoint = self.endpoint.as_deref().unwrap_or("server");
+
+        match self.state
+        {
+            ConnectionState::Offline => "Offline".to_string(),
+            ConnectionState::Connecting => format!("Connecting to {}...", endpoint),
+            ConnectionState::Backoff { retry_in } => format!("Connection lost, retrying in {}s", (retry_in / 1000.0).ceil()),
+            ConnectionState::Closed => match &self.handshake
+            {
+                Handshake::Refused { reason } => format!("Disconnected: {}", reason),
+                _ => format!("Couldn't connect to {}", endpoint)
+            },
+            ConnectionState::Open => match &self.handshake
+            {
+                Handshake::NotStarted | Handshake::AwaitingWelcome { .. } => "Waiting for the server...".to_string(),
+                Handshake::Established { protocol_version, capabilities: _ } => match self.round_trip_time
+                {
+                    Some(rtt) => format!("Connected to {} (protocol v{}, {} ms)", endpoint, protocol_version, rtt.round()),
+                    None => format!("Connected to {} (protocol v{})", endpoint, protocol_version)
+                },
+                Handshake::Legacy => "Connected to an older server - some features may be missing".to_string(),
+                Handshake::Refused { reason } => format!("Disconnected: {}", reason)
+            }
+        }
+    }
+
+    //Sends the player's update if it has moved since the last one (or it's time for a keepalive),
+    //no more often than the current update interval allows
+    pub fn send_update_if_needed(&mut self, message: &Message, delta_time: f32)
+    {
+        self.time_since_last_update += delta_time;
+
+        if !self.is_ready() || self.time_since_last_update < self.update_interval
+        {
+            return;
+        }
+
+        let position = glm::vec2(message.x().unwrap_or(0.0), message.y().unwrap_or(0.0));
+
+        let moved = match self.last_sent_position
+        {
+            Some(p) => glm::distance(&p, &position) > UPDATE_POSITION_THRESHOLD,
+            None => true
+        };
+
+        //e.g. turning around or stopping, which peers should see even if the position barely changed
+        let motion_changed = match (message.motion_state(), &self.last_sent_motion_state)
+        {
+            (Some(current), Some(last)) => current.looks_different_from(last),
+            (None, None) => false,
+            _ => true
+        };
+
+        let changed = moved || motion_changed;
+
+        if !changed && self.time_since_last_update < KEEPALIVE_INTERVAL
+        {
+            return;
+        }
+
+        //The connection hasn't caught up with what we already sent. More would only queue up behind it, so back off.
+        if self.get_buffered_amount() > CONGESTED_BUFFERED_AMOUNT
+        {
+            self.update_interval = (self.update_interval * 2.0).min(MAX_UPDATE_INTERVAL);
+            return;
+        }
+
+        self.time_since_last_update = 0.0;
+        self.last_sent_position = Some(position);
+        self.last_sent_motion_state = message.motion_state().cloned();
+        self.immediately_send_message(message);
+
+        self.adapt_update_interval();
+    }
+
+    //Sends less often on slow connections, where updates would mostly arrive bunched up anyway.
+    //After backing off for congestion, speeds back up gradually rather than all at once.
+    fn adapt_update_interval(&mut self)
+    {
+        let target = match self.round_trip_time
+        {
+            Some(rtt) => (rtt * 0.5).max(MIN_UPDATE_INTERVAL).min(MAX_UPDATE_INTERVAL),
+            None => MIN_UPDATE_INTERVAL
+        };
+
+        self.update_interval = (self.update_interval * 0.75).max(target);
+    }
+
+    fn get_buffered_amount(&self) -> u32
+    {
+        match self.transport.as_ref()
+        {
+            Some(t) => t.buffered_amount(),
+            None => 0
+        }
+    }
+
+    //Nothing is sent until both sides know they speak the same protocol
+    fn is_ready(&self) -> bool
+    {
+        matches!(self.handshake, Handshake::Established { .. } | Handshake::Legacy)
+    }
+
+    pub fn immediately_send_message(&mut self, message: &Message)
+    {
+        if !self.is_ready()
+        {
+            return;
+        }
+
+        if matches!(message.message_type(), MessageType::ChatMessage) && !self.has_capability(CAPABILITY_CHAT)
+        {
+            log("Server doesn't support chat, message not sent");
+            return;
+        }
+
+        self.send_bytes(&message.to_bytes());
+    }
+
+    fn send_bytes(&mut self, message_byte_array: &[u8])
+    {
+        if let Some(transport) = self.transport.as_mut()
+        {
+            transport.send(message_byte_array);
         }
     }
 
     pub fn receive_inbound_messages(&mut self, functor: &mut dyn FnMut(&Message))
     {
-        let mut locked_queue = self.inbound_message_queue.lock().unwrap();
+        let messages = std::mem::take(&mut self.inbound_message_queue);
 
-        for i in locked_queue.iter()
+        for i in messages.iter()
         {
+            //Latency measurements are handled here, everything else is up to the caller
+            if i.message_type() == MessageType::Pong
+            {
+                self.process_pong(i);
+                continue;
+            }
+
             functor(i);
         }
-
-        locked_queue.clear();
     }
 }
\ No newline at end of file
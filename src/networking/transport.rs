@@ -0,0 +1,30 @@
+//What happened on a transport since it was last polled
+pub enum TransportEvent
+{
+    Opened,
+    Message(Vec<u8>),
+    Closed { code: u16, reason: String },
+    Error //Always followed by a Closed
+}
+
+//A connection to a server that moves raw message bytes. ServerConnection handles everything above that.
+pub trait Transport
+{
+    //Returns false if the bytes couldn't be sent, e.g. the transport isn't open yet
+    fn send(&mut self, bytes: &[u8]) -> bool;
+
+    //Everything that has happened since the last poll, oldest first
+    fn poll_events(&mut self) -> Vec<TransportEvent>;
+
+    //Bytes queued to send that haven't gone out yet. Keeps growing if we send faster than the connection can take.
+    fn buffered_amount(&self) -> u32
+    {
+        0
+    }
+
+    //Closes without raising a Closed event, since the caller asked for it
+    fn close(&mut self);
+}
+
+//Opens a new transport to the given endpoint, or None if that isn't possible (e.g. a malformed endpoint)
+pub type TransportFactory = Box<dyn FnMut(&str) -> Option<Box<dyn Transport>>>;
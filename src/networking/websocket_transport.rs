@@ -0,0 +1,144 @@
+use web_sys::WebSocket;
+use web_sys::{MessageEvent, CloseEvent, Event};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::js_sys::{ArrayBuffer,Uint8Array};
+use web_sys::BinaryType;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use crate::networking::transport::{Transport, TransportEvent, TransportFactory};
+use crate::util::logging::log;
+
+//A browser WebSocket. Its callbacks queue events which are handed out when polled.
+//The callbacks are owned here rather than leaked, so they're freed along with the transport.
+pub struct WebSocketTransport
+{
+    socket: WebSocket,
+    events: Arc<Mutex<VecDeque<TransportEvent>>>,
+    _onmessage_callback: Closure<dyn FnMut(MessageEvent)>,
+    _onopen_callback: Closure<dyn FnMut(Event)>,
+    _onclose_callback: Closure<dyn FnMut(CloseEvent)>,
+    _onerror_callback: Closure<dyn FnMut(Event)>
+}
+
+impl WebSocketTransport
+{
+    pub fn open(endpoint: &str) -> Option<Self>
+    {
+        let socket = match WebSocket::new(endpoint)
+        {
+            Ok(ws) => ws,
+            Err(_) => 
+            {
+                log(&format!("Failed to open WebSocket connection to {}",endpoint));
+                return None;
+            }
+        };
+
+        let events = Arc::new(Mutex::new(VecDeque::new()));
+
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let events_ref = events.clone();
+        let onmessage_callback = Closure::<dyn FnMut(_)>::new(move |e: MessageEvent| {
+            if let Ok(abuf) = e.data().dyn_into::<ArrayBuffer>() {
+                //TODO unnecessary copy?
+                events_ref.lock().unwrap().push_back(TransportEvent::Message(Uint8Array::new(&abuf).to_vec()));
+            } else
+            {
+                log("Failed to convert inbound message data to arraybuffer");
+            }
+        });
+        socket.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+
+        let events_ref = events.clone();
+        let onopen_callback = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+            events_ref.lock().unwrap().push_back(TransportEvent::Opened);
+        });
+        socket.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+
+        let events_ref = events.clone();
+        let onclose_callback = Closure::<dyn FnMut(_)>::new(move |e: CloseEvent| {
+            events_ref.lock().unwrap().push_back(TransportEvent::Closed { code: e.code(), reason: e.reason() });
+        });
+        socket.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+
+        let events_ref = events.clone();
+        let onerror_callback = Closure::<dyn FnMut(_)>::new(move |_: Event| {
+            events_ref.lock().unwrap().push_back(TransportEvent::Error);
+        });
+        socket.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+
+        Some(Self
+        {
+            socket,
+            events,
+            _onmessage_callback: onmessage_callback,
+            _onopen_callback: onopen_callback,
+            _onclose_callback: onclose_callback,
+            _onerror_callback: onerror_callback
+        })
+    }
+
+    pub fn factory() -> TransportFactory
+    {
+        Box::new(|endpoint: &str| -> Option<Box<dyn Transport>>
+        {
+            WebSocketTransport::open(endpoint).map(|t| Box::new(t) as Box<dyn Transport>)
+        })
+    }
+}
+
+impl Transport for WebSocketTransport
+{
+    fn buffered_amount(&self) -> u32
+    {
+        self.socket.buffered_amount()
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> bool
+    {
+        if self.socket.ready_state() != WebSocket::OPEN
+        {
+            return false;
+        }
+
+        let buffer = ArrayBuffer::new(bytes.len() as u32);
+        Uint8Array::new(&buffer).copy_from(bytes);
+
+        match self.socket.send_with_array_buffer(&buffer)
+        {
+            Ok(_) => true,
+            Err(err) => {
+                log(format!("error sending message: {:?}", err).as_str());
+                false
+            }
+        }
+    }
+
+    fn poll_events(&mut self) -> Vec<TransportEvent>
+    {
+        self.events.lock().unwrap().drain(..).collect()
+    }
+
+    fn close(&mut self)
+    {
+        self.socket.set_onmessage(None);
+        self.socket.set_onopen(None);
+        self.socket.set_onclose(None);
+        self.socket.set_onerror(None);
+        let _ = self.socket.close();
+
+        self.events.lock().unwrap().clear();
+    }
+}
+
+//The socket must stop calling the callbacks before they are freed
+impl Drop for WebSocketTransport
+{
+    fn drop(&mut self)
+    {
+        self.close();
+    }
+}
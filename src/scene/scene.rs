@@ -7,13 +7,15 @@ use core::cell::Ref;
 use core::cell::RefMut;
 use crate::component::component::Component;
 use crate::component::component_buffer::ComponentBuffer;
+use crate::networking::peer_id::PeerId;
 use crate::util::logging::log;
 
 pub struct Scene
 {
     next_entity_uid: usize,
     component_buffer_map: HashMap<TypeId,RefCell<Box<dyn Any + 'static>>>,
-    peer_entity_uid_map: HashMap<String,usize>
+    peer_entity_uid_map: HashMap<PeerId,usize>,
+    local_peer_id: Option<PeerId> //Our own id on the server, mapped to the player's entity
 }
 
 impl Scene
@@ -25,6 +27,7 @@ impl Scene
             next_entity_uid: 0,
             component_buffer_map: HashMap::new(),
             peer_entity_uid_map: HashMap::new(),
+            local_peer_id: None
         }
     }
 
@@ -137,6 +140,50 @@ impl Scene
         }
     }
 
+    //Same as apply_to_entities_with, but the functor also receives the entity uid
+    pub fn apply_to_entities_with_uid<T: Component, F>(&mut self, mut functor: F)
+    where
+        F: FnMut(usize, &mut T)
+    {
+        let set: Vec<usize>;
+
+        {
+            let buffer = match Self::get_component_buffer::<T>(&self.component_buffer_map)
+            {
+                Some(a) => a,
+                None => { return; }
+            };
+
+            set = buffer.get_entity_set().iter().cloned().collect();
+        }
+
+        let mut buffer = match Self::get_mut_component_buffer::<T>(&mut self.component_buffer_map)
+        {
+            Some(a) => a,
+            None => { return; }
+        };
+
+        for entity_uid in set
+        {
+            let component_instance = match buffer.get_mut(entity_uid)
+            {
+                Some(a) => a,
+                None => { continue; }
+            };
+
+            functor(entity_uid, component_instance);
+        }
+    }
+
+    pub fn has_component<T: Component>(&self, entity_uid: usize) -> bool
+    {
+        match Self::get_component_buffer::<T>(&self.component_buffer_map)
+        {
+            Some(buffer) => buffer.get(entity_uid).is_some(),
+            None => false
+        }
+    }
+
     pub fn apply_to_entity<T: Component, F>(&mut self, entity_uid: usize, mut functor: F)
     where
         F: FnMut(&mut T)
@@ -169,9 +216,9 @@ impl Scene
         return Some(self.next_entity_uid);
     }
 
-    pub fn add_entity_for_peer(&mut self, uuid: &String) -> Option<usize>
+    pub fn add_entity_for_peer(&mut self, peer_id: &PeerId) -> Option<usize>
     {
-        if self.peer_entity_uid_map.contains_key(uuid)
+        if self.peer_entity_uid_map.contains_key(peer_id)
         {
             return None;
         }
@@ -182,25 +229,56 @@ impl Scene
             None => { return None; }
         };
 
-        self.peer_entity_uid_map.insert(uuid.clone(),entity_uid.unwrap().clone());
+        self.peer_entity_uid_map.insert(*peer_id,entity_uid.unwrap().clone());
 
         entity_uid
     }
 
-    pub fn get_entity_for_peer(&mut self, uuid: &String) -> Option<&usize>
+    pub fn get_entity_for_peer(&mut self, peer_id: &PeerId) -> Option<&usize>
+    {
+        self.peer_entity_uid_map.get(peer_id)
+    }
+
+    //Links the server's id for this client to an existing entity (the player), so messages about us find it
+    pub fn set_local_peer(&mut self, peer_id: &PeerId, entity_uid: usize)
+    {
+        self.clear_local_peer();
+
+        self.peer_entity_uid_map.insert(*peer_id, entity_uid);
+        self.local_peer_id = Some(*peer_id);
+    }
+
+    pub fn clear_local_peer(&mut self)
+    {
+        if let Some(previous) = self.local_peer_id.take()
+        {
+            self.peer_entity_uid_map.remove(&previous);
+        }
+    }
+
+    //Every remote peer we have an entity for (not including ourselves)
+    pub fn get_remote_peers(&self) -> Vec<(PeerId,usize)>
+    {
+        self.peer_entity_uid_map.iter()
+            .filter(|(peer_id, _)| self.local_peer_id.as_ref() != Some(*peer_id))
+            .map(|(peer_id, entity_uid)| (*peer_id, *entity_uid))
+            .collect()
+    }
+
+    pub fn is_local_peer(&self, peer_id: &PeerId) -> bool
     {
-        self.peer_entity_uid_map.get(uuid)
+        self.local_peer_id.as_ref() == Some(peer_id)
     }
 
     //NB: this does NOT remove component data for this entity.
-    pub fn remove_entity_for_peer(&mut self, uuid: &String)
+    pub fn remove_entity_for_peer(&mut self, peer_id: &PeerId)
     {
-        if !self.peer_entity_uid_map.contains_key(uuid)
+        if !self.peer_entity_uid_map.contains_key(peer_id)
         {
             return;
         }
 
-        self.peer_entity_uid_map.remove(uuid);
+        self.peer_entity_uid_map.remove(peer_id);
     }
 
     pub fn add_component<T: Component>(&mut self, entity_uid: usize, component: T)
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+//Things the player can do. Systems ask about these rather than about raw keys or buttons.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Action
+{
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Chat,
+    Interact,
+    ZoomIn,
+    ZoomOut
+}
+
+impl Action
+{
+    pub const ALL : [Action; 7] = [Action::MoveLeft, Action::MoveRight, Action::Jump, Action::Chat, Action::Interact, Action::ZoomIn, Action::ZoomOut];
+
+    pub fn from_name(name: &str) -> Option<Action>
+    {
+        match name
+        {
+            "MoveLeft" => Some(Action::MoveLeft),
+            "MoveRight" => Some(Action::MoveRight),
+            "Jump" => Some(Action::Jump),
+            "Chat" => Some(Action::Chat),
+            "Interact" => Some(Action::Interact),
+            "ZoomIn" => Some(Action::ZoomIn),
+            "ZoomOut" => Some(Action::ZoomOut),
+            _ => None
+        }
+    }
+}
+
+//A physical input that can trigger an action
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub enum InputBinding
+{
+    Key(String), //KeyboardEvent.code, e.g. "KeyW"
+    PointerButton(i16), //PointerEvent.button, e.g. 0 for the primary button
+    GamepadButton(u32), //Index into Gamepad.buttons (standard mapping)
+    GamepadAxisNegative(u32), //Index into Gamepad.axes, pushed towards -1.0 (left/up)
+    GamepadAxisPositive(u32) //Index into Gamepad.axes, pushed towards 1.0 (right/down)
+}
+
+pub struct ActionMap
+{
+    bindings: HashMap<Action,Vec<InputBinding>>
+}
+
+impl ActionMap
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            bindings: HashMap::from([
+                (Action::MoveLeft, vec![
+                    InputBinding::Key("KeyA".to_string()),
+                    InputBinding::Key("ArrowLeft".to_string()),
+                    InputBinding::GamepadButton(14),
+                    InputBinding::GamepadAxisNegative(0)
+                ]),
+                (Action::MoveRight, vec![
+                    InputBinding::Key("KeyD".to_string()),
+                    InputBinding::Key("ArrowRight".to_string()),
+                    InputBinding::GamepadButton(15),
+                    InputBinding::GamepadAxisPositive(0)
+                ]),
+                (Action::Jump, vec![
+                    InputBinding::Key("KeyW".to_string()),
+                    InputBinding::Key("ArrowUp".to_string()),
+                    InputBinding::Key("Space".to_string()),
+                    InputBinding::GamepadButton(0)
+                ]),
+                (Action::Chat, vec![
+                    InputBinding::Key("Enter".to_string())
+                ]),
+                (Action::Interact, vec![
+                    InputBinding::Key("KeyE".to_string()),
+                    InputBinding::GamepadButton(2)
+                ]),
+                (Action::ZoomIn, vec![
+                    InputBinding::Key("Equal".to_string()),
+                    InputBinding::Key("NumpadAdd".to_string())
+                ]),
+                (Action::ZoomOut, vec![
+                    InputBinding::Key("Minus".to_string()),
+                    InputBinding::Key("NumpadSubtract".to_string())
+                ]),
+            ])
+        }
+    }
+
+    pub fn bind(&mut self, action: Action, binding: InputBinding)
+    {
+        let bindings = self.bindings.entry(action).or_default();
+
+        if bindings.contains(&binding)
+        {
+            return;
+        }
+
+        bindings.push(binding);
+    }
+
+    pub fn clear_bindings(&mut self, action: Action)
+    {
+        self.bindings.remove(&action);
+    }
+
+    pub fn get_bindings(&self, action: Action) -> &[InputBinding]
+    {
+        match self.bindings.get(&action)
+        {
+            Some(b) => b.as_slice(),
+            None => &[]
+        }
+    }
+}
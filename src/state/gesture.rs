@@ -0,0 +1,196 @@
+use std::collections::{HashMap, VecDeque};
+
+//Thresholds are in milliseconds and canvas pixels
+static TAP_MAX_DURATION : f32 = 250.0;
+static TAP_MAX_DISTANCE : f32 = 10.0;
+static LONG_PRESS_DURATION : f32 = 500.0;
+static SWIPE_MIN_DISTANCE : f32 = 50.0;
+static SWIPE_MAX_DURATION : f32 = 500.0;
+
+#[derive(Clone, Copy)]
+pub enum Gesture
+{
+    Tap,
+    LongPress,
+    Swipe,
+    Pinch { scale: f32, center: glm::Vec2 } //Scale is relative to the previous pinch gesture, > 1.0 means the fingers moved apart. Center is canvas pixels from the bottom left.
+}
+
+struct TrackedPointer
+{
+    start_position: glm::Vec2,
+    position: glm::Vec2,
+    start_time: f32,
+    long_pressed: bool,
+    pinched: bool //Part of a multi-finger gesture, so it can't also be a tap/swipe
+}
+
+//Tracks each pointer (finger, mouse, pen) by id and turns their movement into gestures
+pub struct GestureRecognizer
+{
+    pointers: HashMap<i32,TrackedPointer>,
+    gestures: VecDeque<Gesture>,
+    time: f32,
+    last_pinch_distance: Option<f32>
+}
+
+impl GestureRecognizer
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            pointers: HashMap::new(),
+            gestures: VecDeque::new(),
+            time: 0.0,
+            last_pinch_distance: None
+        }
+    }
+
+    pub fn get_next_gesture(&mut self) -> Option<Gesture>
+    {
+        self.gestures.pop_front()
+    }
+
+    //Advances the recognizer's clock. Long presses are detected here since they happen without any pointer event.
+    pub fn update(&mut self, delta_time: f32)
+    {
+        self.time += delta_time;
+
+        for pointer in self.pointers.values_mut()
+        {
+            if pointer.long_pressed || pointer.pinched
+            {
+                continue;
+            }
+
+            if self.time - pointer.start_time < LONG_PRESS_DURATION
+            {
+                continue;
+            }
+
+            if glm::distance(&pointer.start_position, &pointer.position) > TAP_MAX_DISTANCE
+            {
+                continue;
+            }
+
+            pointer.long_pressed = true;
+            self.gestures.push_back(Gesture::LongPress);
+        }
+    }
+
+    pub fn pointer_down(&mut self, pointer_id: i32, position: glm::Vec2)
+    {
+        self.pointers.insert(pointer_id, TrackedPointer
+        {
+            start_position: position,
+            position,
+            start_time: self.time,
+            long_pressed: false,
+            pinched: false
+        });
+
+        //A second finger turns this into a pinch
+        if self.pointers.len() == 2
+        {
+            for pointer in self.pointers.values_mut()
+            {
+                pointer.pinched = true;
+            }
+
+            self.last_pinch_distance = self.get_pinch_distance_and_center().map(|(distance, _)| distance);
+        }
+    }
+
+    pub fn pointer_move(&mut self, pointer_id: i32, position: glm::Vec2)
+    {
+        match self.pointers.get_mut(&pointer_id)
+        {
+            Some(p) => { p.position = position; },
+            None => { return; }
+        };
+
+        let (distance, center) = match self.get_pinch_distance_and_center()
+        {
+            Some(p) => p,
+            None => { return; }
+        };
+
+        match self.last_pinch_distance
+        {
+            Some(last_distance) if last_distance > 0.0 && distance > 0.0 => {
+                self.gestures.push_back(Gesture::Pinch { scale: distance / last_distance, center });
+            },
+            _ => {}
+        };
+
+        self.last_pinch_distance = Some(distance);
+    }
+
+    //Returns what the pointer turned out to be, if it was anything on its own
+    pub fn pointer_up(&mut self, pointer_id: i32, position: glm::Vec2) -> Option<Gesture>
+    {
+        let pointer = match self.pointers.remove(&pointer_id)
+        {
+            Some(p) => p,
+            None => { return None; }
+        };
+
+        if self.pointers.len() < 2
+        {
+            self.last_pinch_distance = None;
+        }
+
+        if pointer.pinched || pointer.long_pressed
+        {
+            return None;
+        }
+
+        let duration = self.time - pointer.start_time;
+        let distance = glm::distance(&position, &pointer.start_position);
+
+        let gesture = if duration <= TAP_MAX_DURATION && distance <= TAP_MAX_DISTANCE
+        {
+            Gesture::Tap
+        } else if duration <= SWIPE_MAX_DURATION && distance >= SWIPE_MIN_DISTANCE
+        {
+            Gesture::Swipe
+        } else
+        {
+            return None;
+        };
+
+        self.gestures.push_back(gesture);
+
+        Some(gesture)
+    }
+
+    //The browser took the pointer away (e.g. to scroll the page), so it ends without being a gesture
+    pub fn pointer_cancel(&mut self, pointer_id: i32)
+    {
+        if self.pointers.remove(&pointer_id).is_none()
+        {
+            return;
+        }
+
+        if self.pointers.len() < 2
+        {
+            self.last_pinch_distance = None;
+        }
+    }
+
+    //Only defined while exactly two pointers are down
+    fn get_pinch_distance_and_center(&self) -> Option<(f32,glm::Vec2)>
+    {
+        if self.pointers.len() != 2
+        {
+            return None;
+        }
+
+        let mut positions = self.pointers.values().map(|p| p.position);
+        let a = positions.next()?;
+        let b = positions.next()?;
+
+        Some((glm::distance(&a, &b), (a + b) * 0.5))
+    }
+}
@@ -0,0 +1,557 @@
+use crate::state::action_map::Action;
+use std::convert::TryInto;
+
+//"PWIR" - possum world input recording
+static RECORDING_MAGIC : [u8; 4] = [0x50, 0x57, 0x49, 0x52];
+static RECORDING_VERSION : u8 = 2;
+
+//A raw input event, exactly as it was passed in to InputState
+#[derive(Clone, PartialEq, Debug)]
+pub enum InputEvent
+{
+    Key { pressed: bool, code: String, key: String },
+    Click { start: bool, x: i32, y: i32, button: i16, pointer_id: i32 },
+    MouseMove { x: i32, y: i32, pointer_id: i32 },
+    Scroll { delta_x: f32, delta_y: f32 },
+    Gamepad { axes: Vec<f32>, buttons: Vec<f32> },
+    CanvasDimensions { x: u32, y: u32 },
+    PointerCancel { pointer_id: i32 }
+}
+
+impl InputEvent
+{
+    fn kind_id(&self) -> u8
+    {
+        match self
+        {
+            InputEvent::Key { .. } => 0,
+            InputEvent::Click { .. } => 1,
+            InputEvent::MouseMove { .. } => 2,
+            InputEvent::Scroll { .. } => 3,
+            InputEvent::Gamepad { .. } => 4,
+            InputEvent::CanvasDimensions { .. } => 5,
+            InputEvent::PointerCancel { .. } => 6
+        }
+    }
+}
+
+//The input state when a recording started: whatever was held, anything that happened since the last snapshot,
+//and the parts of that snapshot the next one is compared against to find what was just pressed or released.
+#[derive(Clone, PartialEq, Debug)]
+pub struct InputStartState
+{
+    pub canvas_size: (u32,u32),
+    pub pressed_keys: Vec<String>,
+    pub just_pressed_keys: Vec<String>,
+    pub just_released_keys: Vec<String>,
+    pub pressed_pointer_buttons: Vec<i16>,
+    pub just_pressed_pointer_buttons: Vec<i16>,
+    pub just_released_pointer_buttons: Vec<i16>,
+    pub scroll_delta: (f32,f32),
+    pub gamepad_axes: Vec<f32>, //Raw, before deadzones
+    pub gamepad_buttons: Vec<f32>,
+    pub pointer_position: (i32,i32), //Canvas pixels from the bottom left
+    pub pointer_active: bool,
+    pub primary_pointer_id: Option<i32>,
+    pub text_entry: Option<String>,
+    pub previous_active_actions: Vec<Action>,
+    pub previous_pointer_position: (f32,f32)
+}
+
+//Every input event of a session stamped with the tick it arrived before, plus each tick's delta time.
+//Starting from the same state and feeding the events back on the same ticks with the same delta times reproduces the session.
+#[derive(Clone, PartialEq, Debug)]
+pub struct InputRecording
+{
+    start_state: InputStartState,
+    events: Vec<(u32,InputEvent)>,
+    delta_times: Vec<f32>
+}
+
+impl InputRecording
+{
+    pub fn new(start_state: InputStartState) -> Self
+    {
+        Self
+        {
+            start_state,
+            events: Vec::new(),
+            delta_times: Vec::new()
+        }
+    }
+
+    pub fn get_start_state(&self) -> &InputStartState
+    {
+        &self.start_state
+    }
+
+    pub fn push_event(&mut self, tick: u32, event: InputEvent)
+    {
+        self.events.push((tick, event));
+    }
+
+    pub fn push_delta_time(&mut self, delta_time: f32)
+    {
+        self.delta_times.push(delta_time);
+    }
+
+    pub fn get_events(&self) -> &Vec<(u32,InputEvent)>
+    {
+        &self.events
+    }
+
+    pub fn get_delta_time(&self, tick: u32) -> Option<f32>
+    {
+        self.delta_times.get(tick as usize).copied()
+    }
+
+    pub fn get_tick_count(&self) -> u32
+    {
+        self.delta_times.len() as u32
+    }
+
+    //4 (magic) + 1 (version) + start state + 4 (tick count) + 4 per tick + 4 (event count) + per event: 4 (tick) + 1 (kind) + payload
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::<u8>::new();
+
+        bytes.extend_from_slice(&RECORDING_MAGIC);
+        bytes.push(RECORDING_VERSION);
+
+        write_start_state(&mut bytes, &self.start_state);
+
+        bytes.extend_from_slice(&(self.delta_times.len() as u32).to_le_bytes());
+
+        for delta_time in &self.delta_times
+        {
+            bytes.extend_from_slice(&delta_time.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+
+        for (tick, event) in &self.events
+        {
+            bytes.extend_from_slice(&tick.to_le_bytes());
+            bytes.push(event.kind_id());
+
+            match event
+            {
+                InputEvent::Key { pressed, code, key } => {
+                    bytes.push(*pressed as u8);
+                    write_string(&mut bytes, code);
+                    write_string(&mut bytes, key);
+                },
+                InputEvent::Click { start, x, y, button, pointer_id } => {
+                    bytes.push(*start as u8);
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                    bytes.extend_from_slice(&y.to_le_bytes());
+                    bytes.extend_from_slice(&button.to_le_bytes());
+                    bytes.extend_from_slice(&pointer_id.to_le_bytes());
+                },
+                InputEvent::MouseMove { x, y, pointer_id } => {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                    bytes.extend_from_slice(&y.to_le_bytes());
+                    bytes.extend_from_slice(&pointer_id.to_le_bytes());
+                },
+                InputEvent::Scroll { delta_x, delta_y } => {
+                    bytes.extend_from_slice(&delta_x.to_le_bytes());
+                    bytes.extend_from_slice(&delta_y.to_le_bytes());
+                },
+                InputEvent::Gamepad { axes, buttons } => {
+                    write_f32s(&mut bytes, axes);
+                    write_f32s(&mut bytes, buttons);
+                },
+                InputEvent::CanvasDimensions { x, y } => {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                    bytes.extend_from_slice(&y.to_le_bytes());
+                },
+                InputEvent::PointerCancel { pointer_id } => {
+                    bytes.extend_from_slice(&pointer_id.to_le_bytes());
+                }
+            };
+        }
+
+        bytes
+    }
+
+    //None if the bytes aren't a complete recording of a version we understand
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self>
+    {
+        let mut reader = RecordingReader { bytes, offset: 0 };
+
+        if reader.take(4)? != RECORDING_MAGIC || reader.read_u8()? != RECORDING_VERSION
+        {
+            return None;
+        }
+
+        let mut recording = InputRecording::new(read_start_state(&mut reader)?);
+
+        let tick_count = reader.read_u32()?;
+
+        for _ in 0..tick_count
+        {
+            recording.push_delta_time(reader.read_f32()?);
+        }
+
+        let event_count = reader.read_u32()?;
+
+        for _ in 0..event_count
+        {
+            let tick = reader.read_u32()?;
+
+            let event = match reader.read_u8()?
+            {
+                0 => InputEvent::Key {
+                    pressed: reader.read_u8()? != 0,
+                    code: reader.read_string()?,
+                    key: reader.read_string()?
+                },
+                1 => InputEvent::Click {
+                    start: reader.read_u8()? != 0,
+                    x: reader.read_i32()?,
+                    y: reader.read_i32()?,
+                    button: i16::from_le_bytes(reader.take(2)?.try_into().ok()?),
+                    pointer_id: reader.read_i32()?
+                },
+                2 => InputEvent::MouseMove {
+                    x: reader.read_i32()?,
+                    y: reader.read_i32()?,
+                    pointer_id: reader.read_i32()?
+                },
+                3 => InputEvent::Scroll {
+                    delta_x: reader.read_f32()?,
+                    delta_y: reader.read_f32()?
+                },
+                4 => InputEvent::Gamepad {
+                    axes: reader.read_f32s()?,
+                    buttons: reader.read_f32s()?
+                },
+                5 => InputEvent::CanvasDimensions {
+                    x: reader.read_u32()?,
+                    y: reader.read_u32()?
+                },
+                6 => InputEvent::PointerCancel {
+                    pointer_id: reader.read_i32()?
+                },
+                _ => { return None; }
+            };
+
+            recording.push_event(tick, event);
+        }
+
+        Some(recording)
+    }
+}
+
+//2 (length) + utf-8 bytes
+fn write_string(bytes: &mut Vec<u8>, string: &str)
+{
+    bytes.extend_from_slice(&(string.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+//1 (count) + 4 per value
+fn write_f32s(bytes: &mut Vec<u8>, values: &[f32])
+{
+    bytes.push(values.len() as u8);
+    values.iter().for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+}
+
+//2 (count) + strings
+fn write_strings(bytes: &mut Vec<u8>, strings: &[String])
+{
+    bytes.extend_from_slice(&(strings.len() as u16).to_le_bytes());
+    strings.iter().for_each(|s| write_string(bytes, s));
+}
+
+//1 (count) + 2 per button
+fn write_pointer_buttons(bytes: &mut Vec<u8>, buttons: &[i16])
+{
+    bytes.push(buttons.len() as u8);
+    buttons.iter().for_each(|b| bytes.extend_from_slice(&b.to_le_bytes()));
+}
+
+fn write_start_state(bytes: &mut Vec<u8>, start_state: &InputStartState)
+{
+    bytes.extend_from_slice(&start_state.canvas_size.0.to_le_bytes());
+    bytes.extend_from_slice(&start_state.canvas_size.1.to_le_bytes());
+    write_strings(bytes, &start_state.pressed_keys);
+    write_strings(bytes, &start_state.just_pressed_keys);
+    write_strings(bytes, &start_state.just_released_keys);
+    write_pointer_buttons(bytes, &start_state.pressed_pointer_buttons);
+    write_pointer_buttons(bytes, &start_state.just_pressed_pointer_buttons);
+    write_pointer_buttons(bytes, &start_state.just_released_pointer_buttons);
+    bytes.extend_from_slice(&start_state.scroll_delta.0.to_le_bytes());
+    bytes.extend_from_slice(&start_state.scroll_delta.1.to_le_bytes());
+    write_f32s(bytes, &start_state.gamepad_axes);
+    write_f32s(bytes, &start_state.gamepad_buttons);
+    bytes.extend_from_slice(&start_state.pointer_position.0.to_le_bytes());
+    bytes.extend_from_slice(&start_state.pointer_position.1.to_le_bytes());
+    bytes.push(start_state.pointer_active as u8);
+
+    //1 (present) + 4 (id)
+    bytes.push(start_state.primary_pointer_id.is_some() as u8);
+    bytes.extend_from_slice(&start_state.primary_pointer_id.unwrap_or(0).to_le_bytes());
+
+    //1 (present) + string
+    bytes.push(start_state.text_entry.is_some() as u8);
+    write_string(bytes, start_state.text_entry.as_deref().unwrap_or(""));
+
+    //1 (count) + 1 per action, its index in Action::ALL
+    bytes.push(start_state.previous_active_actions.len() as u8);
+    for action in &start_state.previous_active_actions
+    {
+        bytes.push(Action::ALL.iter().position(|a| a == action).unwrap() as u8);
+    }
+
+    bytes.extend_from_slice(&start_state.previous_pointer_position.0.to_le_bytes());
+    bytes.extend_from_slice(&start_state.previous_pointer_position.1.to_le_bytes());
+}
+
+fn read_start_state(reader: &mut RecordingReader) -> Option<InputStartState>
+{
+    Some(InputStartState {
+        canvas_size: (reader.read_u32()?, reader.read_u32()?),
+        pressed_keys: reader.read_strings()?,
+        just_pressed_keys: reader.read_strings()?,
+        just_released_keys: reader.read_strings()?,
+        pressed_pointer_buttons: reader.read_pointer_buttons()?,
+        just_pressed_pointer_buttons: reader.read_pointer_buttons()?,
+        just_released_pointer_buttons: reader.read_pointer_buttons()?,
+        scroll_delta: (reader.read_f32()?, reader.read_f32()?),
+        gamepad_axes: reader.read_f32s()?,
+        gamepad_buttons: reader.read_f32s()?,
+        pointer_position: (reader.read_i32()?, reader.read_i32()?),
+        pointer_active: reader.read_u8()? != 0,
+        primary_pointer_id: {
+            let present = reader.read_u8()? != 0;
+            let id = reader.read_i32()?;
+            if present { Some(id) } else { None }
+        },
+        text_entry: {
+            let present = reader.read_u8()? != 0;
+            let text = reader.read_string()?;
+            if present { Some(text) } else { None }
+        },
+        previous_active_actions: {
+            let count = reader.read_u8()?;
+            (0..count).map(|_| Action::ALL.get(reader.read_u8()? as usize).copied()).collect::<Option<Vec<Action>>>()?
+        },
+        previous_pointer_position: (reader.read_f32()?, reader.read_f32()?)
+    })
+}
+
+struct RecordingReader<'a>
+{
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl<'a> RecordingReader<'a>
+{
+    fn take(&mut self, length: usize) -> Option<&'a [u8]>
+    {
+        let end = self.offset.checked_add(length)?;
+        let slice = self.bytes.get(self.offset..end)?;
+        self.offset = end;
+
+        Some(slice)
+    }
+
+    fn read_string(&mut self) -> Option<String>
+    {
+        let length = u16::from_le_bytes(self.take(2)?.try_into().ok()?) as usize;
+
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+
+    fn read_strings(&mut self) -> Option<Vec<String>>
+    {
+        let count = u16::from_le_bytes(self.take(2)?.try_into().ok()?);
+
+        (0..count).map(|_| self.read_string()).collect()
+    }
+
+    fn read_f32s(&mut self) -> Option<Vec<f32>>
+    {
+        let count = self.read_u8()?;
+
+        (0..count).map(|_| self.read_f32()).collect()
+    }
+
+    fn read_pointer_buttons(&mut self) -> Option<Vec<i16>>
+    {
+        let count = self.read_u8()?;
+
+        (0..count).map(|_| Some(i16::from_le_bytes(self.take(2)?.try_into().ok()?))).collect()
+    }
+
+    fn read_u8(&mut self) -> Option<u8>
+    {
+        Some(self.take(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Option<u32>
+    {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn read_i32(&mut self) -> Option<i32>
+    {
+        Some(i32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn read_f32(&mut self) -> Option<f32>
+    {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::state::action_map::Action;
+    use crate::state::input_state::InputState;
+    use crate::state::input_snapshot::InputSnapshot;
+
+    //Runs a short session, returning the recording and the snapshot seen on every tick
+    fn record_session() -> (InputRecording, Vec<InputSnapshot>)
+    {
+        let mut input_state = InputState::new();
+        input_state.set_canvas_dimensions(800, 600);
+        input_state.start_recording();
+
+        let mut snapshots = Vec::new();
+
+        input_state.process_input(true, "KeyD", "d");
+        input_state.update(16.0);
+        snapshots.push(input_state.get_snapshot().clone());
+
+        input_state.process_mouse_move(100, 200, 1);
+        input_state.process_scroll(0.0, -120.0);
+        input_state.update(17.0);
+        snapshots.push(input_state.get_snapshot().clone());
+
+        //Pressed and released between two ticks
+        input_state.process_input(true, "Space", " ");
+        input_state.process_input(false, "Space", " ");
+        input_state.process_click(true, 300, 100, 0, 1);
+        input_state.process_gamepad_state(&[0.9, 0.0], &[1.0]);
+        input_state.update(15.0);
+        snapshots.push(input_state.get_snapshot().clone());
+
+        input_state.process_input(false, "KeyD", "d");
+        input_state.process_click(false, 300, 100, 0, 1);
+        input_state.update(16.0);
+        snapshots.push(input_state.get_snapshot().clone());
+
+        (input_state.stop_recording().unwrap(), snapshots)
+    }
+
+    #[test]
+    fn recording_survives_serialization()
+    {
+        let (recording, _) = record_session();
+
+        assert_eq!(InputRecording::from_bytes(&recording.to_bytes()), Some(recording));
+    }
+
+    #[test]
+    fn truncated_or_foreign_bytes_are_rejected()
+    {
+        let (recording, _) = record_session();
+        let bytes = recording.to_bytes();
+
+        assert_eq!(InputRecording::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(InputRecording::from_bytes(&[0, 1, 2, 3, 4]), None);
+    }
+
+    #[test]
+    fn playback_reproduces_every_tick()
+    {
+        let (recording, recorded_snapshots) = record_session();
+
+        let mut input_state = InputState::new();
+        input_state.start_playback(InputRecording::from_bytes(&recording.to_bytes()).unwrap());
+
+        for (tick, recorded_snapshot) in recorded_snapshots.iter().enumerate()
+        {
+            let delta_time = input_state.get_playback_delta_time().unwrap();
+            assert_eq!(Some(delta_time), recording.get_delta_time(tick as u32));
+
+            //Live input during playback is ignored
+            input_state.process_input(true, "KeyA", "a");
+
+            input_state.update(delta_time);
+            assert!(input_state.get_snapshot() == recorded_snapshot, "snapshot differs on tick {}", tick);
+        }
+
+        assert!(!input_state.is_playing_back());
+        assert!(recorded_snapshots[2].is_action_just_pressed(Action::Jump));
+        assert!(recorded_snapshots[2].is_action_just_released(Action::Jump));
+    }
+
+    #[test]
+    fn playback_starts_from_what_was_held_and_then_hands_back_to_live_input()
+    {
+        let mut input_state = InputState::new();
+        input_state.set_canvas_dimensions(800, 600);
+        input_state.process_input(true, "Space", " ");
+        input_state.process_gamepad_state(&[0.9, 0.0], &[]);
+        input_state.update(16.0);
+
+        //Pressed after the last snapshot, so still to show up as just pressed
+        input_state.process_input(true, "KeyA", "a");
+        input_state.start_recording();
+
+        let mut recorded_snapshots = Vec::new();
+
+        input_state.update(16.0);
+        recorded_snapshots.push(input_state.get_snapshot().clone());
+
+        input_state.update(16.0);
+        recorded_snapshots.push(input_state.get_snapshot().clone());
+
+        input_state.process_input(false, "Space", " ");
+        input_state.process_gamepad_state(&[0.0, 0.0], &[]);
+        input_state.update(16.0);
+        recorded_snapshots.push(input_state.get_snapshot().clone());
+
+        let recording = InputRecording::from_bytes(&input_state.stop_recording().unwrap().to_bytes()).unwrap();
+
+        assert!(recorded_snapshots[0].is_action_active(Action::Jump) && !recorded_snapshots[0].is_action_just_pressed(Action::Jump));
+        assert!(recorded_snapshots[0].is_action_active(Action::MoveRight));
+        assert!(recorded_snapshots[0].is_action_just_pressed(Action::MoveLeft));
+        assert!(recorded_snapshots[2].is_action_just_released(Action::Jump));
+        assert!(recorded_snapshots[2].is_action_just_released(Action::MoveRight));
+
+        //Played back on a different canvas, with different keys held
+        let mut input_state = InputState::new();
+        input_state.set_canvas_dimensions(1024, 768);
+        input_state.process_input(true, "KeyW", "w");
+        input_state.update(16.0);
+        input_state.start_playback(recording);
+
+        for (tick, recorded_snapshot) in recorded_snapshots.iter().enumerate()
+        {
+            if tick == 1
+            {
+                input_state.process_input(false, "KeyW", "w");
+                input_state.process_input(true, "KeyD", "d");
+            }
+
+            let delta_time = input_state.get_playback_delta_time().unwrap();
+            input_state.update(delta_time);
+            assert!(input_state.get_snapshot() == recorded_snapshot, "snapshot differs on tick {}", tick);
+        }
+
+        assert!(!input_state.is_playing_back());
+
+        //Live input picks up where it left off, including what changed during playback
+        input_state.update(16.0);
+        assert!(input_state.get_snapshot().is_action_active(Action::MoveRight));
+        assert!(input_state.get_snapshot().is_action_just_released(Action::Jump));
+        assert_eq!(input_state.get_canvas_ratio(&glm::vec2(1024.0, 768.0)), glm::vec2(1.0, 1.0));
+    }
+}
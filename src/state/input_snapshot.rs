@@ -0,0 +1,146 @@
+use crate::state::action_map::Action;
+use std::collections::HashSet;
+
+//The input state as of one tick. Committed once at the start of each tick so every system sees the same input.
+//"Just pressed" and "just released" cover everything since the previous snapshot, so a press and release
+//that both happen between two ticks still show up as just pressed and just released.
+#[derive(Clone, PartialEq)]
+pub struct InputSnapshot
+{
+    pressed_keys: HashSet<String>,
+    just_pressed_keys: HashSet<String>,
+    just_released_keys: HashSet<String>,
+    pressed_pointer_buttons: HashSet<i16>,
+    just_pressed_pointer_buttons: HashSet<i16>,
+    just_released_pointer_buttons: HashSet<i16>,
+    active_actions: HashSet<Action>,
+    just_pressed_actions: HashSet<Action>,
+    just_released_actions: HashSet<Action>,
+    pointer_position: glm::Vec2, //Canvas pixels from the bottom left
+    pointer_delta: glm::Vec2,
+    scroll_delta: glm::Vec2 //WheelEvent deltas, positive y means scrolling down/towards the user
+}
+
+impl InputSnapshot
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            pressed_keys: HashSet::new(),
+            just_pressed_keys: HashSet::new(),
+            just_released_keys: HashSet::new(),
+            pressed_pointer_buttons: HashSet::new(),
+            just_pressed_pointer_buttons: HashSet::new(),
+            just_released_pointer_buttons: HashSet::new(),
+            active_actions: HashSet::new(),
+            just_pressed_actions: HashSet::new(),
+            just_released_actions: HashSet::new(),
+            pointer_position: glm::vec2(0.0,0.0),
+            pointer_delta: glm::vec2(0.0,0.0),
+            scroll_delta: glm::vec2(0.0,0.0)
+        }
+    }
+
+    pub fn set_keys(&mut self, pressed: HashSet<String>, just_pressed: HashSet<String>, just_released: HashSet<String>)
+    {
+        self.pressed_keys = pressed;
+        self.just_pressed_keys = just_pressed;
+        self.just_released_keys = just_released;
+    }
+
+    pub fn set_pointer_buttons(&mut self, pressed: HashSet<i16>, just_pressed: HashSet<i16>, just_released: HashSet<i16>)
+    {
+        self.pressed_pointer_buttons = pressed;
+        self.just_pressed_pointer_buttons = just_pressed;
+        self.just_released_pointer_buttons = just_released;
+    }
+
+    pub fn set_actions(&mut self, active: HashSet<Action>, just_pressed: HashSet<Action>, just_released: HashSet<Action>)
+    {
+        self.active_actions = active;
+        self.just_pressed_actions = just_pressed;
+        self.just_released_actions = just_released;
+    }
+
+    pub fn set_pointer(&mut self, position: glm::Vec2, delta: glm::Vec2)
+    {
+        self.pointer_position = position;
+        self.pointer_delta = delta;
+    }
+
+    pub fn set_scroll_delta(&mut self, scroll_delta: glm::Vec2)
+    {
+        self.scroll_delta = scroll_delta;
+    }
+
+    pub fn is_key_pressed(&self, code: &str) -> bool
+    {
+        self.pressed_keys.contains(code)
+    }
+
+    pub fn is_key_just_pressed(&self, code: &str) -> bool
+    {
+        self.just_pressed_keys.contains(code)
+    }
+
+    pub fn is_key_just_released(&self, code: &str) -> bool
+    {
+        self.just_released_keys.contains(code)
+    }
+
+    pub fn get_pressed_keys(&self) -> &HashSet<String>
+    {
+        &self.pressed_keys
+    }
+
+    pub fn is_pointer_button_pressed(&self, button: i16) -> bool
+    {
+        self.pressed_pointer_buttons.contains(&button)
+    }
+
+    pub fn is_pointer_button_just_pressed(&self, button: i16) -> bool
+    {
+        self.just_pressed_pointer_buttons.contains(&button)
+    }
+
+    pub fn is_pointer_button_just_released(&self, button: i16) -> bool
+    {
+        self.just_released_pointer_buttons.contains(&button)
+    }
+
+    pub fn get_pressed_pointer_buttons(&self) -> &HashSet<i16>
+    {
+        &self.pressed_pointer_buttons
+    }
+
+    pub fn is_action_active(&self, action: Action) -> bool
+    {
+        self.active_actions.contains(&action)
+    }
+
+    pub fn is_action_just_pressed(&self, action: Action) -> bool
+    {
+        self.just_pressed_actions.contains(&action)
+    }
+
+    pub fn is_action_just_released(&self, action: Action) -> bool
+    {
+        self.just_released_actions.contains(&action)
+    }
+
+    pub fn get_pointer_position(&self) -> &glm::Vec2
+    {
+        &self.pointer_position
+    }
+
+    pub fn get_pointer_delta(&self) -> &glm::Vec2
+    {
+        &self.pointer_delta
+    }
+
+    pub fn get_scroll_delta(&self) -> &glm::Vec2
+    {
+        &self.scroll_delta
+    }
+}
@@ -1,6 +1,16 @@
-use crate::util::logging::log;
-use std::collections::{HashMap, VecDeque};
-use std::fmt;
+use crate::state::action_map::{Action, ActionMap, InputBinding};
+use crate::state::gesture::{Gesture, GestureRecognizer};
+use crate::state::input_snapshot::InputSnapshot;
+use crate::state::input_recording::{InputEvent, InputRecording, InputStartState};
+use std::collections::{HashSet, VecDeque};
+
+//Sticks rarely rest at exactly 0, so ignore anything this close to the center
+static GAMEPAD_AXIS_DEADZONE : f32 = 0.2;
+
+//Analog buttons (e.g. triggers) count as pressed past this value
+static GAMEPAD_BUTTON_THRESHOLD : f32 = 0.5;
+
+static MAX_TEXT_ENTRY_LENGTH : usize = 200;
 
 #[derive(Copy)]
 #[derive(Clone)]
@@ -57,52 +67,78 @@ impl Click
         &&self.canvas_ratio_x
     }
 
+    pub fn get_canvas_ratio_y(&self) -> &f32
+    {
+        &self.canvas_ratio_y
+    }
+
     pub fn is_active(&self) -> bool
     {
         self.active
     }
 }
 
+enum RecordingMode
+{
+    Off,
+    Recording(InputRecording),
+    Playback { recording: InputRecording, next_event: usize, live_state: Box<InputState> } //The live state is put back once playback ends
+}
+
 pub struct InputState
 {
-    active: HashMap<KeyPress,bool>,
+    action_map: ActionMap,
+    pressed_keys: HashSet<String>,
+    just_pressed_keys: HashSet<String>, //Since the last snapshot
+    just_released_keys: HashSet<String>,
+    pressed_pointer_buttons: HashSet<i16>,
+    just_pressed_pointer_buttons: HashSet<i16>,
+    just_released_pointer_buttons: HashSet<i16>,
+    scroll_delta: glm::Vec2, //Accumulated since the last snapshot
+    snapshot: InputSnapshot,
+    recording_mode: RecordingMode,
+    tick: u32, //Ticks since recording or playback started
+    text_entry: Option<String>, //The line being typed, while text entry is active
+    text_entry_changed: bool,
+    submitted_text: VecDeque<String>,
+    pressed_gamepad_buttons: HashSet<u32>,
+    gamepad_axes: Vec<f32>,
+    raw_gamepad_state: (Vec<f32>,Vec<f32>), //As last reported, before deadzones
     click_locations: VecDeque<Click>,
     last_mouse_location: Click,
+    primary_pointer_id: Option<i32>, //The first pointer down drives last_mouse_location, others only feed gestures
+    gesture_recognizer: GestureRecognizer,
     canvas_size_x: u32,
     canvas_size_y: u32,
 }
 
-#[derive(Hash)]
-#[derive(PartialEq)]
-#[derive(Eq)]
-pub enum KeyPress
-{
-    W,S,A,D
-}
-
-impl fmt::Display for KeyPress
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result 
-    {
-        match self 
-        {
-            KeyPress::W => write!(f, "W"),
-            KeyPress::S => write!(f, "S"),
-            KeyPress::A => write!(f, "A"),
-            KeyPress::D => write!(f, "D"),
-        }
-    }
-}
-
 impl InputState
 {
     pub fn new() -> Self
     {
         Self
         {
-            active: HashMap::from([(KeyPress::W, false),(KeyPress::S, false),(KeyPress::A, false), (KeyPress::D, false)]),
+            action_map: ActionMap::new(),
+            pressed_keys: HashSet::new(),
+            just_pressed_keys: HashSet::new(),
+            just_released_keys: HashSet::new(),
+            pressed_pointer_buttons: HashSet::new(),
+            just_pressed_pointer_buttons: HashSet::new(),
+            just_released_pointer_buttons: HashSet::new(),
+            scroll_delta: glm::vec2(0.0,0.0),
+            snapshot: InputSnapshot::new(),
+            recording_mode: RecordingMode::Off,
+            tick: 0,
+            text_entry: None,
+            text_entry_changed: false,
+            submitted_text: VecDeque::new(),
+            pressed_gamepad_buttons: HashSet::new(),
+            gamepad_axes: Vec::new(),
+            raw_gamepad_state: (Vec::new(), Vec::new()),
             click_locations: VecDeque::new(),
             last_mouse_location: Click::new(),
+            primary_pointer_id: None,
+            gesture_recognizer: GestureRecognizer::new(),
             canvas_size_x: 1,
             canvas_size_y: 1,
         }
@@ -129,97 +165,685 @@ impl InputState
         None
     }
 
+    //Converts canvas pixels from the bottom left (as used by gestures) to 0..1 on each axis
+    pub fn get_canvas_ratio(&self, position: &glm::Vec2) -> glm::Vec2
+    {
+        glm::vec2(position.x / self.canvas_size_x as f32, position.y / self.canvas_size_y as f32)
+    }
+
     pub fn get_current_mouse_location(&self) -> &Click
     {
         &self.last_mouse_location
     }
 
-    pub fn is_pressed(&self, key : KeyPress) -> bool
+    pub fn is_action_active(&self, action: Action) -> bool
+    {
+        self.get_action_strength(action) > 0.0
+    }
+
+    //0.0 to 1.0. Digital inputs are all or nothing, gamepad axes give partial values.
+    pub fn get_action_strength(&self, action: Action) -> f32
     {
-        match self.active.get(&key)
+        //Typing shouldn't also move the player around
+        if self.is_text_entry_active()
         {
-            Some(r) => return *r,
-            None => {}
+            return 0.0;
+        }
+
+        self.action_map.get_bindings(action).iter().map(|binding| self.get_binding_strength(binding)).fold(0.0, f32::max)
+    }
+
+    //-1.0 to 1.0 along an axis made from two opposing actions. Both or neither being active cancels out.
+    pub fn get_action_axis(&self, negative: Action, positive: Action) -> f32
+    {
+        self.get_action_strength(positive) - self.get_action_strength(negative)
+    }
+
+    fn get_binding_strength(&self, binding: &InputBinding) -> f32
+    {
+        let pressed = match binding
+        {
+            InputBinding::Key(code) => self.pressed_keys.contains(code),
+            InputBinding::PointerButton(button) => self.pressed_pointer_buttons.contains(button),
+            InputBinding::GamepadButton(button) => self.pressed_gamepad_buttons.contains(button),
+            InputBinding::GamepadAxisNegative(axis) => {
+                return (-self.get_gamepad_axis(*axis)).max(0.0);
+            },
+            InputBinding::GamepadAxisPositive(axis) => {
+                return self.get_gamepad_axis(*axis).max(0.0);
+            }
         };
 
-        false
+        if pressed { 1.0 } else { 0.0 }
     }
 
-    pub fn process_click(&mut self, start_or_end_click: bool, x: i32, y: i32)
+    fn get_gamepad_axis(&self, axis: u32) -> f32
+    {
+        match self.gamepad_axes.get(axis as usize)
+        {
+            Some(a) => *a,
+            None => 0.0
+        }
+    }
+
+    pub fn get_mut_action_map(&mut self) -> &mut ActionMap
+    {
+        &mut self.action_map
+    }
+
+    pub fn process_click(&mut self, start_or_end_click: bool, x: i32, y: i32, button: i16, pointer_id: i32)
+    {
+        self.process_event(InputEvent::Click { start: start_or_end_click, x, y, button, pointer_id });
+    }
+
+    fn apply_click(&mut self, start_or_end_click: bool, x: i32, y: i32, button: i16, pointer_id: i32)
     {
         let inverted_y = self.canvas_size_y as i32 - y;
+        let position = glm::vec2(x as f32, inverted_y as f32);
 
         //Started click
         if start_or_end_click
         {
+            self.pressed_pointer_buttons.insert(button);
+            self.just_pressed_pointer_buttons.insert(button);
+            self.gesture_recognizer.pointer_down(pointer_id, position);
+
+            if self.primary_pointer_id.is_some()
+            {
+                //Another finger joined in - this is a multi-touch gesture, not a click or a hold
+                self.last_mouse_location.set_active(false);
+                return;
+            }
+
+            self.primary_pointer_id = Some(pointer_id);
             self.last_mouse_location.set_active(true);
             self.last_mouse_location.set_x_coordinate(x);
             self.last_mouse_location.set_y_coordinate(inverted_y);
             self.last_mouse_location.set_canvas_ratio_x(x as f32 / self.canvas_size_x as f32);
             self.last_mouse_location.set_canvas_ratio_y(inverted_y as f32 / self.canvas_size_y as f32);
+            return;
+        }
 
+        //Ended click
+        self.pressed_pointer_buttons.remove(&button);
+        self.just_released_pointer_buttons.insert(button);
+        let gesture = self.gesture_recognizer.pointer_up(pointer_id, position);
+
+        if self.primary_pointer_id != Some(pointer_id)
+        {
+            return;
+        }
+
+        //Only a quick press and release counts as a click, not the start of a swipe, pinch or long press
+        if self.last_mouse_location.is_active() && matches!(gesture, Some(Gesture::Tap))
+        {
             self.click_locations.push_back(self.last_mouse_location.clone());
+        }
+
+        self.primary_pointer_id = None;
+        self.last_mouse_location.set_active(false);
+    }
+
+    pub fn process_pointer_cancel(&mut self, pointer_id: i32)
+    {
+        self.process_event(InputEvent::PointerCancel { pointer_id });
+    }
+
+    fn apply_pointer_cancel(&mut self, pointer_id: i32)
+    {
+        self.gesture_recognizer.pointer_cancel(pointer_id);
+
+        if self.primary_pointer_id != Some(pointer_id)
+        {
             return;
         }
 
-        //Ended click
+        //Cancellations don't say which button was down, but the primary pointer is the one holding them
+        for button in self.pressed_pointer_buttons.drain()
+        {
+            self.just_released_pointer_buttons.insert(button);
+        }
+
+        self.primary_pointer_id = None;
         self.last_mouse_location.set_active(false);
     }
 
-    pub fn process_mouse_move(&mut self, x: i32, y: i32)
+    pub fn process_mouse_move(&mut self, x: i32, y: i32, pointer_id: i32)
+    {
+        self.process_event(InputEvent::MouseMove { x, y, pointer_id });
+    }
+
+    fn apply_mouse_move(&mut self, x: i32, y: i32, pointer_id: i32)
     {
         let inverted_y = self.canvas_size_y as i32 - y;
 
+        self.gesture_recognizer.pointer_move(pointer_id, glm::vec2(x as f32, inverted_y as f32));
+
+        if self.primary_pointer_id.is_some() && self.primary_pointer_id != Some(pointer_id)
+        {
+            return;
+        }
+
         self.last_mouse_location.set_x_coordinate(x);
         self.last_mouse_location.set_y_coordinate(inverted_y);
         self.last_mouse_location.set_canvas_ratio_x(x as f32 / self.canvas_size_x as f32);
         self.last_mouse_location.set_canvas_ratio_y(inverted_y as f32 / self.canvas_size_y as f32);
     }
 
+    pub fn process_scroll(&mut self, delta_x: f32, delta_y: f32)
+    {
+        self.process_event(InputEvent::Scroll { delta_x, delta_y });
+    }
+
+    //Called once per tick, before gestures or the snapshot are read
+    pub fn update(&mut self, delta_time: f32)
+    {
+        self.apply_playback_events();
+
+        let playback_finished = match &mut self.recording_mode
+        {
+            RecordingMode::Recording(recording) => {
+                recording.push_delta_time(delta_time);
+                false
+            },
+            RecordingMode::Playback { recording, next_event: _, live_state: _ } => self.tick + 1 >= recording.get_tick_count(),
+            RecordingMode::Off => false
+        };
+
+        self.gesture_recognizer.update(delta_time);
+        self.commit_snapshot();
+
+        self.tick += 1;
+
+        if playback_finished
+        {
+            self.stop_playback();
+        }
+    }
+
+    //Live events are recorded if recording, or go to the live state during playback so they can't interfere with it
+    fn process_event(&mut self, event: InputEvent)
+    {
+        match &mut self.recording_mode
+        {
+            RecordingMode::Recording(recording) => { recording.push_event(self.tick, event.clone()); },
+            RecordingMode::Playback { recording: _, next_event: _, live_state } => {
+                live_state.process_event(event);
+                return;
+            },
+            RecordingMode::Off => {}
+        };
+
+        self.apply_event(event);
+    }
+
+    fn apply_event(&mut self, event: InputEvent)
+    {
+        match event
+        {
+            InputEvent::Key { pressed, code, key } => self.apply_input(pressed, &code, &key),
+            InputEvent::Click { start, x, y, button, pointer_id } => self.apply_click(start, x, y, button, pointer_id),
+            InputEvent::MouseMove { x, y, pointer_id } => self.apply_mouse_move(x, y, pointer_id),
+            InputEvent::Scroll { delta_x, delta_y } => { self.scroll_delta += glm::vec2(delta_x, delta_y); },
+            InputEvent::Gamepad { axes, buttons } => self.apply_gamepad_state(&axes, &buttons),
+            InputEvent::CanvasDimensions { x, y } => self.apply_canvas_dimensions(x, y),
+            InputEvent::PointerCancel { pointer_id } => self.apply_pointer_cancel(pointer_id)
+        };
+    }
+
+    //Feeds in every recorded event that arrived before the current tick
+    fn apply_playback_events(&mut self)
+    {
+        let mut events = Vec::new();
+
+        if let RecordingMode::Playback { recording, next_event, live_state: _ } = &mut self.recording_mode
+        {
+            let recorded_events = recording.get_events();
+
+            while *next_event < recorded_events.len() && recorded_events[*next_event].0 <= self.tick
+            {
+                events.push(recorded_events[*next_event].1.clone());
+                *next_event += 1;
+            }
+        }
+
+        for event in events
+        {
+            self.apply_event(event);
+        }
+    }
+
+    //Starts a new recording. The current state is recorded first so playback starts from the same place.
+    pub fn start_recording(&mut self)
+    {
+        if self.is_playing_back()
+        {
+            return;
+        }
+
+        self.tick = 0;
+        self.recording_mode = RecordingMode::Recording(InputRecording::new(self.get_start_state()));
+    }
+
+    //Returns the recording, or None if nothing was being recorded
+    pub fn stop_recording(&mut self) -> Option<InputRecording>
+    {
+        match std::mem::replace(&mut self.recording_mode, RecordingMode::Off)
+        {
+            RecordingMode::Recording(recording) => Some(recording),
+            other => {
+                self.recording_mode = other;
+                None
+            }
+        }
+    }
+
+    //Puts the live input aside (but not the bindings) and replays the recording from its start state, one recorded tick per update.
+    //Live input carries on being tracked during playback and takes over again once it ends.
+    pub fn start_playback(&mut self, recording: InputRecording)
+    {
+        self.stop_playback();
+
+        let action_map = std::mem::replace(&mut self.action_map, ActionMap::new());
+        let live_state = std::mem::replace(self, InputState::new());
+
+        self.action_map = action_map;
+        self.apply_start_state(recording.get_start_state());
+        self.recording_mode = RecordingMode::Playback { recording, next_event: 0, live_state: Box::new(live_state) };
+    }
+
+    fn stop_playback(&mut self)
+    {
+        let live_state = match std::mem::replace(&mut self.recording_mode, RecordingMode::Off)
+        {
+            RecordingMode::Playback { recording: _, next_event: _, live_state } => live_state,
+            other => {
+                self.recording_mode = other;
+                return;
+            }
+        };
+
+        let action_map = std::mem::replace(&mut self.action_map, ActionMap::new());
+
+        //Systems last saw the final played back tick, so the next live snapshot's edges are relative to that
+        let snapshot = std::mem::replace(&mut self.snapshot, InputSnapshot::new());
+
+        *self = *live_state;
+
+        self.action_map = action_map;
+        self.snapshot = snapshot;
+
+        //Whatever the recording was typing gives way to the live line
+        self.text_entry_changed = true;
+    }
+
+    fn get_start_state(&self) -> InputStartState
+    {
+        InputStartState {
+            canvas_size: (self.canvas_size_x, self.canvas_size_y),
+            pressed_keys: self.pressed_keys.iter().cloned().collect(),
+            just_pressed_keys: self.just_pressed_keys.iter().cloned().collect(),
+            just_released_keys: self.just_released_keys.iter().cloned().collect(),
+            pressed_pointer_buttons: self.pressed_pointer_buttons.iter().cloned().collect(),
+            just_pressed_pointer_buttons: self.just_pressed_pointer_buttons.iter().cloned().collect(),
+            just_released_pointer_buttons: self.just_released_pointer_buttons.iter().cloned().collect(),
+            scroll_delta: (self.scroll_delta.x, self.scroll_delta.y),
+            gamepad_axes: self.raw_gamepad_state.0.clone(),
+            gamepad_buttons: self.raw_gamepad_state.1.clone(),
+            pointer_position: (self.last_mouse_location.x_coordinate, self.last_mouse_location.y_coordinate),
+            pointer_active: self.last_mouse_location.is_active(),
+            primary_pointer_id: self.primary_pointer_id,
+            text_entry: self.text_entry.clone(),
+            previous_active_actions: Action::ALL.iter().cloned().filter(|action| self.snapshot.is_action_active(*action)).collect(),
+            previous_pointer_position: (self.snapshot.get_pointer_position().x, self.snapshot.get_pointer_position().y)
+        }
+    }
+
+    fn apply_start_state(&mut self, start_state: &InputStartState)
+    {
+        self.apply_canvas_dimensions(start_state.canvas_size.0, start_state.canvas_size.1);
+
+        self.pressed_keys = start_state.pressed_keys.iter().cloned().collect();
+        self.just_pressed_keys = start_state.just_pressed_keys.iter().cloned().collect();
+        self.just_released_keys = start_state.just_released_keys.iter().cloned().collect();
+        self.pressed_pointer_buttons = start_state.pressed_pointer_buttons.iter().cloned().collect();
+        self.just_pressed_pointer_buttons = start_state.just_pressed_pointer_buttons.iter().cloned().collect();
+        self.just_released_pointer_buttons = start_state.just_released_pointer_buttons.iter().cloned().collect();
+        self.scroll_delta = glm::vec2(start_state.scroll_delta.0, start_state.scroll_delta.1);
+        self.apply_gamepad_state(&start_state.gamepad_axes, &start_state.gamepad_buttons);
+
+        let (x, y) = start_state.pointer_position;
+        self.last_mouse_location.set_x_coordinate(x);
+        self.last_mouse_location.set_y_coordinate(y);
+        self.last_mouse_location.set_canvas_ratio_x(x as f32 / self.canvas_size_x as f32);
+        self.last_mouse_location.set_canvas_ratio_y(y as f32 / self.canvas_size_y as f32);
+        self.last_mouse_location.set_active(start_state.pointer_active);
+        self.primary_pointer_id = start_state.primary_pointer_id;
+
+        self.text_entry = start_state.text_entry.clone();
+        self.text_entry_changed = true;
+
+        self.snapshot.set_actions(start_state.previous_active_actions.iter().cloned().collect(), HashSet::new(), HashSet::new());
+        self.snapshot.set_pointer(glm::vec2(start_state.previous_pointer_position.0, start_state.previous_pointer_position.1), glm::vec2(0.0,0.0));
+    }
+
+    pub fn is_playing_back(&self) -> bool
+    {
+        matches!(self.recording_mode, RecordingMode::Playback { .. })
+    }
+
+    //The delta time the current tick had when it was recorded. Systems should use this instead of the real one during playback.
+    pub fn get_playback_delta_time(&self) -> Option<f32>
+    {
+        match &self.recording_mode
+        {
+            RecordingMode::Playback { recording, next_event: _, live_state: _ } => recording.get_delta_time(self.tick),
+            _ => None
+        }
+    }
+
+    pub fn get_snapshot(&self) -> &InputSnapshot
+    {
+        &self.snapshot
+    }
+
+    fn commit_snapshot(&mut self)
+    {
+        let mut active_actions = HashSet::new();
+        let mut just_pressed_actions = HashSet::new();
+        let mut just_released_actions = HashSet::new();
+
+        for action in Action::ALL
+        {
+            let active = self.is_action_active(action);
+            let was_active = self.snapshot.is_action_active(action);
+
+            //Bindings that went down and up again between ticks still count as an edge
+            let (pressed_between, released_between) = self.get_binding_edges(action);
+
+            if active
+            {
+                active_actions.insert(action);
+            }
+
+            if (active && !was_active) || pressed_between
+            {
+                just_pressed_actions.insert(action);
+            }
+
+            if (!active && was_active) || released_between
+            {
+                just_released_actions.insert(action);
+            }
+        }
+
+        let pointer_position = glm::vec2(self.last_mouse_location.x_coordinate as f32, self.last_mouse_location.y_coordinate as f32);
+        let pointer_delta = pointer_position - self.snapshot.get_pointer_position();
+
+        self.snapshot.set_keys(self.pressed_keys.clone(), std::mem::take(&mut self.just_pressed_keys), std::mem::take(&mut self.just_released_keys));
+        self.snapshot.set_pointer_buttons(self.pressed_pointer_buttons.clone(), std::mem::take(&mut self.just_pressed_pointer_buttons), std::mem::take(&mut self.just_released_pointer_buttons));
+        self.snapshot.set_actions(active_actions, just_pressed_actions, just_released_actions);
+        self.snapshot.set_pointer(pointer_position, pointer_delta);
+        self.snapshot.set_scroll_delta(std::mem::replace(&mut self.scroll_delta, glm::vec2(0.0,0.0)));
+    }
+
+    //Whether any key or pointer button bound to the action was pressed/released since the last snapshot
+    fn get_binding_edges(&self, action: Action) -> (bool, bool)
+    {
+        let mut pressed = false;
+        let mut released = false;
+
+        for binding in self.action_map.get_bindings(action)
+        {
+            match binding
+            {
+                InputBinding::Key(code) => {
+                    pressed |= self.just_pressed_keys.contains(code);
+                    released |= self.just_released_keys.contains(code);
+                },
+                InputBinding::PointerButton(button) => {
+                    pressed |= self.just_pressed_pointer_buttons.contains(button);
+                    released |= self.just_released_pointer_buttons.contains(button);
+                },
+                _ => {}
+            };
+        }
+
+        (pressed, released)
+    }
+
+    pub fn get_next_gesture(&mut self) -> Option<Gesture>
+    {
+        self.gesture_recognizer.get_next_gesture()
+    }
+
     pub fn set_canvas_dimensions(&mut self, canvas_size_x: u32, canvas_size_y: u32)
+    {
+        self.process_event(InputEvent::CanvasDimensions { x: canvas_size_x, y: canvas_size_y });
+    }
+
+    fn apply_canvas_dimensions(&mut self, canvas_size_x: u32, canvas_size_y: u32)
     {
         self.canvas_size_x = canvas_size_x;
         self.canvas_size_y = canvas_size_y;
     }
 
-    pub fn process_input(&mut self, pressed: bool, code: &str)
+    //Takes the full state of a gamepad, as reported each frame by the browser Gamepad API.
+    //Empty arrays (e.g. no gamepad connected) release everything.
+    pub fn process_gamepad_state(&mut self, axes: &[f32], buttons: &[f32])
     {
-        match code
+        //Unchanged is judged against the live gamepad, not the one being played back
+        if let RecordingMode::Playback { recording: _, next_event: _, live_state } = &mut self.recording_mode
         {
-            "KeyW" => 
-            {
-                self.active.insert(KeyPress::W,pressed);
-            },
-            "KeyS" => 
-            {
-                self.active.insert(KeyPress::S,pressed);
-            },
-            "KeyA" => 
+            live_state.process_gamepad_state(axes, buttons);
+            return;
+        }
+
+        //The page reports the gamepad every frame, so skip unchanged states to keep recordings small
+        if self.raw_gamepad_state.0 == axes && self.raw_gamepad_state.1 == buttons
+        {
+            return;
+        }
+
+        self.process_event(InputEvent::Gamepad { axes: axes.to_vec(), buttons: buttons.to_vec() });
+    }
+
+    fn apply_gamepad_state(&mut self, axes: &[f32], buttons: &[f32])
+    {
+        self.raw_gamepad_state = (axes.to_vec(), buttons.to_vec());
+
+        self.gamepad_axes.clear();
+
+        for axis in axes
+        {
+            let magnitude = axis.abs();
+
+            if magnitude < GAMEPAD_AXIS_DEADZONE
             {
-                self.active.insert(KeyPress::A,pressed);
-            },
-            "KeyD" => 
+                self.gamepad_axes.push(0.0);
+                continue;
+            }
+
+            //Rescale so that values start from 0 at the edge of the deadzone
+            let rescaled = ((magnitude - GAMEPAD_AXIS_DEADZONE) / (1.0 - GAMEPAD_AXIS_DEADZONE)).min(1.0);
+            self.gamepad_axes.push(rescaled * axis.signum());
+        }
+
+        self.pressed_gamepad_buttons.clear();
+
+        for (index, value) in buttons.iter().enumerate()
+        {
+            if *value > GAMEPAD_BUTTON_THRESHOLD
             {
-                self.active.insert(KeyPress::D,pressed);
-            },
-            _ => {}
+                self.pressed_gamepad_buttons.insert(index as u32);
+            }
         }
+    }
+
+    //code is KeyboardEvent.code (the physical key), key is KeyboardEvent.key (what it types)
+    pub fn process_input(&mut self, pressed: bool, code: &str, key: &str)
+    {
+        self.process_event(InputEvent::Key { pressed, code: code.to_string(), key: key.to_string() });
+    }
+
+    fn apply_input(&mut self, pressed: bool, code: &str, key: &str)
+    {
+        if pressed && self.is_text_entry_active()
+        {
+            //Keys held since before text entry started only repeat, they aren't typing
+            if !self.pressed_keys.contains(code)
+            {
+                self.apply_text_entry_key(code, key);
+            }
 
-        //TODO: debugging
-        let mut debug_output_string = "".to_owned();
+            return;
+        }
 
-        for (k,v) in &self.active
+        if pressed
         {
-            if *v
+            //Key repeat sends more keydowns while held, which shouldn't count as new presses
+            if self.pressed_keys.insert(code.to_string())
             {
-                debug_output_string.push_str(k.to_string().as_str());
+                self.just_pressed_keys.insert(code.to_string());
             }
+        } else
+        {
+            self.pressed_keys.remove(code);
+            self.just_released_keys.insert(code.to_string());
         }
-        
-        if debug_output_string.len() > 0
+    }
+
+    //While active, key presses type into the line instead of triggering actions
+    pub fn start_text_entry(&mut self)
+    {
+        if self.is_text_entry_active()
         {
-            log(&debug_output_string.as_str());
+            return;
         }
 
+        self.text_entry = Some(String::new());
+        self.text_entry_changed = true;
+    }
+
+    pub fn is_text_entry_active(&self) -> bool
+    {
+        self.text_entry.is_some()
+    }
+
+    pub fn get_text_entry(&self) -> Option<&String>
+    {
+        self.text_entry.as_ref()
+    }
+
+    //True once after each change to the line, including it starting or ending
+    pub fn take_text_entry_changed(&mut self) -> bool
+    {
+        std::mem::replace(&mut self.text_entry_changed, false)
+    }
+
+    //Lines finished with enter, oldest first
+    pub fn get_next_submitted_text(&mut self) -> Option<String>
+    {
+        self.submitted_text.pop_front()
     }
-}
\ No newline at end of file
+
+    fn apply_text_entry_key(&mut self, code: &str, key: &str)
+    {
+        let text = match self.text_entry.as_mut()
+        {
+            Some(t) => t,
+            None => { return; }
+        };
+
+        match code
+        {
+            "Backspace" => {
+                text.pop();
+            },
+            "Enter" | "NumpadEnter" => {
+                if !text.trim().is_empty()
+                {
+                    self.submitted_text.push_back(text.clone());
+                }
+
+                self.text_entry = None;
+            },
+            "Escape" => {
+                self.text_entry = None;
+            },
+            _ => {
+                //Named keys (Shift, ArrowLeft, ...) have multi-character key values
+                if key.chars().count() != 1 || text.chars().count() >= MAX_TEXT_ENTRY_LENGTH
+                {
+                    return;
+                }
+
+                text.push_str(key);
+            }
+        };
+
+        self.text_entry_changed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn a_cancelled_touch_does_not_block_the_next_one()
+    {
+        let mut input_state = InputState::new();
+        input_state.set_canvas_dimensions(800, 600);
+        input_state.get_mut_action_map().bind(Action::Interact, InputBinding::PointerButton(0));
+
+        input_state.process_click(true, 100, 100, 0, 1);
+        input_state.process_pointer_cancel(1);
+        input_state.update(16.0);
+
+        assert!(!input_state.get_snapshot().is_action_active(Action::Interact));
+        assert!(input_state.get_next_gesture().is_none());
+
+        //Not mistaken for a second finger, so it's a tap rather than the start of a pinch
+        input_state.process_click(true, 200, 100, 0, 2);
+        assert!(input_state.get_current_mouse_location().is_active());
+
+        input_state.process_click(false, 200, 100, 0, 2);
+        input_state.update(16.0);
+
+        assert!(matches!(input_state.get_next_gesture(), Some(Gesture::Tap)));
+    }
+
+    #[test]
+    fn only_taps_are_queued_as_clicks_once_released()
+    {
+        let mut input_state = InputState::new();
+        input_state.set_canvas_dimensions(800, 600);
+
+        input_state.process_click(true, 100, 100, 0, 1);
+        assert!(!input_state.has_next_click());
+
+        input_state.process_click(false, 102, 100, 0, 1);
+        let click = input_state.get_next_click().unwrap();
+        assert_eq!((*click.get_canvas_ratio_x(), *click.get_canvas_ratio_y()), (0.125, 500.0 / 600.0));
+
+        //Swipe
+        input_state.process_click(true, 100, 100, 0, 1);
+        input_state.process_mouse_move(300, 100, 1);
+        input_state.process_click(false, 300, 100, 0, 1);
+
+        //Long press
+        input_state.process_click(true, 100, 100, 0, 1);
+        input_state.update(600.0);
+        input_state.process_click(false, 100, 100, 0, 1);
+
+        //Pinch
+        input_state.process_click(true, 100, 100, 0, 1);
+        input_state.process_click(true, 200, 100, 0, 2);
+        input_state.process_click(false, 100, 100, 0, 1);
+        input_state.process_click(false, 200, 100, 0, 2);
+
+        assert!(!input_state.has_next_click());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod input_state;
-pub mod render_state;
\ No newline at end of file
+pub mod render_state;
+pub mod action_map;
+pub mod gesture;
+pub mod input_snapshot;
+pub mod input_recording;
\ No newline at end of file
@@ -27,7 +27,8 @@ pub struct RenderState
     camera: Camera,
     vertex_buffer_map: HashMap<TypeId,Box<dyn Any>>,
     transform_buffer: TransformBuffer,
-    next_uid: u32
+    next_uid: u32,
+    physics_debug_draw: bool
 }
 
 impl RenderState
@@ -46,7 +47,25 @@ impl RenderState
             camera: Camera::new(canvas_size[0],canvas_size[1]),
             vertex_buffer_map: HashMap::new(),
             transform_buffer: transform_buffer,
-            next_uid: 0
+            next_uid: 0,
+            physics_debug_draw: false
+        }
+    }
+
+    //No canvas or GL context - renderables are tracked but nothing is drawn. For running systems in native tests.
+    #[cfg(test)]
+    pub fn new_headless(canvas_width: u32, canvas_height: u32) -> RenderState
+    {
+        Self
+        {
+            context: None,
+            shader: None::<Shader>,
+            textures: HashMap::new(),
+            camera: Camera::new(canvas_width,canvas_height),
+            vertex_buffer_map: HashMap::new(),
+            transform_buffer: TransformBuffer::new(None,"ModelMatrixBlock"),
+            next_uid: 0,
+            physics_debug_draw: false
         }
     }
 
@@ -298,6 +317,44 @@ impl RenderState
         self.transform_buffer.recalculate_transforms_and_update_data(web_context);
     }
 
+    //Get the world space center, size and z of a renderable, given its unscaled size
+    pub fn get_world_bounds(&self, uid: &u32, size: &[i32;2]) -> Option<(glm::Vec2,glm::Vec2,f32)>
+    {
+        let translation = match self.transform_buffer.get_translation(uid)
+        {
+            Some(t) => t,
+            None => { return None; }
+        };
+
+        let scale = match self.transform_buffer.get_scale(uid)
+        {
+            Some(s) => s,
+            None => { return None; }
+        };
+
+        Some((glm::vec2(translation.x,translation.y), glm::vec2(size[0] as f32 * scale.x, size[1] as f32 * scale.y), translation.z))
+    }
+
+    pub fn canvas_ratio_to_world_position(&self, canvas_ratio_x: f32, canvas_ratio_y: f32) -> glm::Vec2
+    {
+        self.camera.canvas_ratio_to_world_position(canvas_ratio_x, canvas_ratio_y)
+    }
+
+    pub fn zoom_camera_towards(&mut self, factor: f32, canvas_ratio_x: f32, canvas_ratio_y: f32)
+    {
+        self.camera.zoom_towards(factor, canvas_ratio_x, canvas_ratio_y);
+    }
+
+    pub fn set_camera_zoom_limits(&mut self, min_zoom: f32, max_zoom: f32)
+    {
+        self.camera.set_zoom_limits(min_zoom, max_zoom);
+    }
+
+    pub fn set_camera_pixel_snapping(&mut self, pixel_snapping: bool)
+    {
+        self.camera.set_pixel_snapping(pixel_snapping);
+    }
+
     pub fn set_camera_world_position(&mut self, position: &glm::Vec2)
     {
         self.camera.set_camera_world_position(position);
@@ -515,9 +572,19 @@ impl RenderState
 
         self.camera.set_canvas_dimensions(x, y);
         
-        //Zoom the camera to an appropriate level based on how big the canvas is.
+        //Zoom the camera to an appropriate level based on how big the canvas is, on top of any zooming by the user.
         //900 is an arbitrary value for a decent zoom
-        self.camera.set_zoom(900.0 / std::cmp::min(x,y) as f32);
+        self.camera.set_base_zoom(900.0 / std::cmp::min(x,y) as f32);
+    }
+
+    pub fn set_physics_debug_draw(&mut self, enabled: bool)
+    {
+        self.physics_debug_draw = enabled;
+    }
+
+    pub fn is_physics_debug_draw_enabled(&self) -> bool
+    {
+        self.physics_debug_draw
     }
 
     pub fn clear(&mut self)
@@ -1,4 +1,6 @@
 use crate::state::input_state::InputState;
+use crate::state::action_map::Action;
+use crate::state::gesture::Gesture;
 use crate::state::render_state::RenderState;
 
 use crate::graphics::draw_batch::DrawBatch;
@@ -6,18 +8,38 @@ use crate::graphics::renderable::Renderable;
 use crate::graphics::animation::{AnimationState,Animation};
 use crate::graphics::sprite::Sprite;
 use crate::graphics::text::Text;
+use crate::graphics::debug_lines::DebugLines;
 use crate::scene::scene::Scene;
 use crate::graphics::font::Font;
-use crate::component::physics_body::PhysicsBody;
-use crate::component::player_input::PlayerInput;
+use crate::component::physics_body::{BodyType, PhysicsBody};
+use crate::component::player_input::{MovementInput, PlayerInput};
 use crate::component::ai::{AIState, AI};
+use crate::component::trigger::{Trigger, TriggerEvent, TriggerEventType};
+use crate::component::clickable::{Clickable, ClickCallback, ClickEvent};
+use crate::component::physics_debug_draw::{PhysicsDebugDraw, VELOCITY_LINE_SCALE};
+use crate::component::platform::{Platform, PlatformType};
+use crate::component::destination_marker::DestinationMarker;
+use crate::component::text_entry_display::TextEntryDisplay;
+use crate::component::peer_interpolation::PeerInterpolation;
+use crate::component::predicted_movement::{PredictedMovement, PredictedTick};
+use crate::component::nametag::Nametag;
+use crate::component::chat_bubble::ChatBubble;
 use crate::component::component::Component;
 use crate::networking::server_connection::ServerConnection;
-use crate::networking::message::{MessageType,Message};
+use crate::networking::message::{MessageType,Message,MotionState,CAPABILITY_RECONCILIATION,CAPABILITY_MOTION_STATE,MOTION_FLAG_GROUNDED};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use rand::Rng;
 use crate::util::logging::log;
+use crate::util::util::rectangles_overlap;
+
+static GRAVITY : f32 = 0.01; //Velocity lost per ms
+static MAX_PHYSICS_STEP : f32 = 50.0; //Longer frames (e.g. after a tab switch) are simulated as this long, so nothing falls through the ground
+static JUMP_SPEED : f32 = 4.0;
+static DESTINATION_ARRIVAL_DISTANCE : f32 = 5.0;
+static TEXT_ENTRY_OFFSET_Y : f32 = 200.0; //Above the player's head, clear of where chat messages show
+static SCROLL_ZOOM_SPEED : f32 = 0.0025; //Doublings of the zoom per scrolled pixel, a typical wheel notch is 100 pixels
+static CHAT_BUBBLE_DURATION : f32 = 5000.0; //ms a chat message stays above whoever said it
 
 //Runs at game start
 pub fn init_scene(scene: &mut Scene)
@@ -61,6 +83,12 @@ pub fn init_scene(scene: &mut Scene)
         None => {return;}
     };
 
+    let sign = match scene.add_entity()
+    {
+        Some(e) => e,
+        None => {return;}
+    };
+
     //BG, ground
     scene.add_component::<Sprite>(bg, Sprite::new_with_position([105,2],[100,100],1, glm::vec2(0.0,0.0),-2.0, glm::vec2(1000.0,1000.0)));
     scene.add_component::<Sprite>(ground, Sprite::new_with_position([2,2],[100,100],1, glm::vec2(0.0,-75.0),-1.0, glm::vec2(100.0,1.0)));
@@ -87,7 +115,7 @@ pub fn init_scene(scene: &mut Scene)
     //NPC Posses
     z = -0.75;
 
-    for _index in 0..rng.gen_range(4..10)
+    for index in 0..rng.gen_range(4..10)
     {
         let poss = match scene.add_entity()
         {
@@ -97,8 +125,13 @@ pub fn init_scene(scene: &mut Scene)
 
         z += 0.002;
 
-        scene.add_component::<PhysicsBody>(poss, PhysicsBody::new_with_position(glm::vec2(rng.gen_range(-250.0..250.0),-25.0)));
+        //Alternating either side of the player, spaced so that nobody starts out overlapping
+        let side = if index % 2 == 0 { 1.0 } else { -1.0 };
+        let x = side * (220.0 + (index / 2) as f32 * 140.0);
+
+        scene.add_component::<PhysicsBody>(poss, PhysicsBody::new_with_body_type(BodyType::Dynamic, glm::vec2(x,-25.0), glm::vec2(116.0,36.0)));
         scene.add_component::<AI>(poss, AI::new());
+        scene.add_component::<Clickable>(poss, Clickable::new(squeak));
         scene.add_component::<Animation::<Sprite>>(poss,Animation::<Sprite>::new(
             HashMap::from([
                 (AnimationState::FacingRight, vec![
@@ -138,7 +171,8 @@ pub fn init_scene(scene: &mut Scene)
 
     //Player Possum ("Barry")
     scene.add_component::<PlayerInput>(player, PlayerInput::new());
-    scene.add_component::<PhysicsBody>(player, PhysicsBody::new());
+    scene.add_component::<PredictedMovement>(player, PredictedMovement::new());
+    scene.add_component::<PhysicsBody>(player, PhysicsBody::new_with_body_type(BodyType::Dynamic, glm::vec2(0.0,0.0), glm::vec2(290.0,90.0)));
     scene.add_component::<Animation::<Sprite>>(player,Animation::<Sprite>::new(
         HashMap::from([
             (AnimationState::FacingRight, vec![
@@ -178,6 +212,189 @@ pub fn init_scene(scene: &mut Scene)
     //Logo Text
     scene.add_component::<Text>(logo, Text::new_with_position("Possum World", &Font::Default, glm::vec2(0.0,350.0), 0.002, glm::vec2(2.0,2.0)));
     scene.add_component::<Text>(logo_subtitle, Text::new_with_position("insert 1 coin to continue", &Font::Default, glm::vec2(0.0,200.0), 0.002, glm::vec2(2.0,2.0)));
+
+    //Marker for where the player is walking to. Starts hidden.
+    let destination_marker = match scene.add_entity()
+    {
+        Some(e) => e,
+        None => {return;}
+    };
+
+    scene.add_component::<DestinationMarker>(destination_marker, DestinationMarker::new());
+    scene.add_component::<Sprite>(destination_marker, Sprite::new_with_position([309,2],[62,46],1, glm::vec2(0.0,0.0), 0.001, glm::vec2(0.0,0.0)));
+
+    //Shows what the player is typing. Gets a Text once they start.
+    let text_entry_display = match scene.add_entity()
+    {
+        Some(e) => e,
+        None => {return;}
+    };
+
+    scene.add_component::<TextEntryDisplay>(text_entry_display, TextEntryDisplay::new());
+
+    //Terrain. The ground platform is invisible - the ground sprite is purely visual.
+    add_platform(scene, Platform::new(PlatformType::Solid), BodyType::Static, glm::vec2(0.0,-95.0), glm::vec2(10000.0,100.0), None);
+
+    //A ledge and some logs to climb onto
+    add_platform(scene, Platform::new(PlatformType::Solid), BodyType::Static, glm::vec2(-750.0,40.0), glm::vec2(300.0,40.0), Some(Sprite::new([2,2],[100,100],1)));
+    add_platform(scene, Platform::new(PlatformType::OneWay), BodyType::Static, glm::vec2(-400.0,30.0), glm::vec2(200.0,20.0), Some(Sprite::new([207,2],[100,100],1)));
+    add_platform(scene, Platform::new(PlatformType::OneWay), BodyType::Static, glm::vec2(-600.0,180.0), glm::vec2(200.0,20.0), Some(Sprite::new([207,2],[100,100],1)));
+
+    //A log that drifts back and forth, carrying anyone riding it
+    add_platform(scene, Platform::new_moving(PlatformType::OneWay, vec![glm::vec2(400.0,40.0), glm::vec2(700.0,120.0)], 0.5), BodyType::Kinematic, glm::vec2(400.0,40.0), glm::vec2(200.0,20.0), Some(Sprite::new([207,2],[100,100],1)));
+
+    //A boulder at the west end of the meadow. Nothing can push past it.
+    let boulder = match scene.add_entity()
+    {
+        Some(e) => e,
+        None => {return;}
+    };
+
+    scene.add_component::<PhysicsBody>(boulder, PhysicsBody::new_with_body_type(BodyType::Static, glm::vec2(-1300.0,55.0), glm::vec2(200.0,200.0)));
+    scene.add_component::<Sprite>(boulder, Sprite::new_with_position([2,2],[100,100],1, glm::vec2(-1300.0,55.0), -0.9, glm::vec2(2.0,2.0)));
+
+    //Sign - shows its text while the player is standing near it, and changes it once they've walked past
+    let mut sign_trigger = Trigger::new(glm::vec2(900.0,0.0), glm::vec2(300.0,300.0));
+    sign_trigger.set_on_enter(update_sign_text);
+    sign_trigger.set_on_stay(update_sign_text);
+    sign_trigger.set_on_exit(update_sign_text);
+    scene.add_component::<Trigger>(sign, sign_trigger);
+}
+
+//Adds a platform entity. The optional sprite is stretched to cover the platform.
+fn add_platform(scene: &mut Scene, platform: Platform, body_type: BodyType, position: glm::Vec2, size: glm::Vec2, sprite: Option<Sprite>)
+{
+    let entity = match scene.add_entity()
+    {
+        Some(e) => e,
+        None => {return;}
+    };
+
+    scene.add_component::<PhysicsBody>(entity, PhysicsBody::new_with_body_type(body_type, position, size));
+    scene.add_component::<Platform>(entity, platform);
+
+    match sprite
+    {
+        Some(s) => {
+            let sprite_size = s.get_size();
+            let scale = glm::vec2(size.x / sprite_size[0] as f32, size.y / sprite_size[1] as f32);
+            scene.add_component::<Sprite>(entity, Sprite::new_with_position(s.get_texture_coordinates(), *sprite_size, s.get_texture_index(), position, -0.9, scale));
+        },
+        None => {}
+    };
+}
+
+fn update_sign_text(scene: &mut Scene, render_state: &mut RenderState, event: &TriggerEvent)
+{
+    let player = event.get_other_entity_uid();
+
+    if !scene.has_component::<PlayerInput>(player)
+    {
+        return;
+    }
+
+    let sign = event.get_trigger_entity_uid();
+
+    match event.get_event_type()
+    {
+        TriggerEventType::Enter | TriggerEventType::Stay => {
+            //The sign greets possums who have made it past
+            let mut player_x = 0.0;
+            scene.apply_to_entity::<PhysicsBody, _>(player, |physics_body: &mut PhysicsBody|
+            {
+                player_x = physics_body.get_position().x;
+            });
+
+            let content = if player_x > 900.0 { "welcome, possum" } else { "possums only past this point" };
+
+            let mut current_content : Option<String> = None;
+            scene.apply_to_entity::<Text, _>(sign,|component: &mut Text|
+            {
+                current_content = Some(component.get_content().clone());
+            });
+
+            if current_content.as_deref() == Some(content)
+            {
+                return;
+            }
+
+            remove_text(scene, render_state, sign);
+
+            scene.add_component::<Text>(sign, Text::new_with_position(content, &Font::Default, glm::vec2(900.0,150.0), 0.002, glm::vec2(1.0,1.0)));
+
+            scene.apply_to_entity::<Text, _>(sign,|component: &mut Text|
+            {
+                render_state.request_new_renderable::<Text>(component);
+            });
+        },
+        TriggerEventType::Exit => {
+            remove_text(scene, render_state, sign);
+        }
+    };
+}
+
+fn remove_text(scene: &mut Scene, render_state: &mut RenderState, entity_uid: usize)
+{
+    if !scene.has_component::<Text>(entity_uid)
+    {
+        return;
+    }
+
+    scene.apply_to_entity::<Text, _>(entity_uid,|component: &mut Text|
+    {
+        render_state.free_renderable(component);
+    });
+
+    scene.remove_component::<Text>(entity_uid);
+}
+
+//Text content can't be changed in place, so this swaps in a new Text above the entity
+fn replace_text(scene: &mut Scene, render_state: &mut RenderState, entity_uid: usize, content: &str)
+{
+    remove_text(scene, render_state, entity_uid);
+
+    scene.add_component::<Text>(entity_uid, Text::new_with_position(content, &Font::Default, glm::vec2(0.0,150.0), 0.002, glm::vec2(1.0,1.0)));
+
+    scene.apply_to_entity::<Text, _>(entity_uid,|component: &mut Text|
+    {
+        render_state.request_new_renderable::<Text>(component);
+    });
+}
+
+fn squeak(scene: &mut Scene, render_state: &mut RenderState, event: &ClickEvent)
+{
+    let poss = event.get_entity_uid();
+
+    if scene.has_component::<Text>(poss)
+    {
+        scene.apply_to_entity::<Text, _>(poss,|component: &mut Text|
+        {
+            render_state.free_renderable(component);
+        });
+
+        scene.remove_component::<Text>(poss);
+        return;
+    }
+
+    scene.add_component::<Text>(poss, Text::new_with_position("squeak", &Font::Default, glm::vec2(0.0,0.0), 0.002, glm::vec2(1.0,1.0)));
+
+    //Startled, it scurries away from wherever it was poked
+    let mut poss_x = 0.0;
+    scene.apply_to_entity::<PhysicsBody, _>(poss, |physics_body: &mut PhysicsBody|
+    {
+        poss_x = physics_body.get_position().x;
+    });
+
+    let poked_from_right = event.get_world_position().x > poss_x;
+    scene.apply_to_entity::<AI, _>(poss, |ai: &mut AI|
+    {
+        ai.set_state(if poked_from_right { AIState::WalkingLeft } else { AIState::WalkingRight });
+    });
+
+    scene.apply_to_entity::<Text, _>(poss,|component: &mut Text|
+    {
+        render_state.request_new_renderable::<Text>(component);
+    });
 }
 
 //Register our renderables and types which own renderables with the renderstate and get their ids set
@@ -186,6 +403,7 @@ pub fn init_render_data_from_scene(scene: &mut Scene, render_state: &mut RenderS
     render_state.clear();
     render_state.clear_buffer::<Sprite>();
     render_state.clear_buffer::<Text>();
+    render_state.clear_buffer::<DebugLines>();
 
     scene.apply_to_entities_with::<Sprite, _>(|component: &mut Sprite|
     {
@@ -279,24 +497,49 @@ pub fn remove_entity(scene: &mut Scene, render_state: &mut RenderState, entity_u
         });
     });
 
+    scene.apply_to_entity::<PhysicsDebugDraw, _>(entity_uid, |component: &mut PhysicsDebugDraw|
+    {
+        component.apply_to_renderables(|renderable: &mut DebugLines|
+        {
+            render_state.free_renderable(renderable);
+        });
+    });
+
     scene.remove_component::<Sprite>(entity_uid);
     scene.remove_component::<Text>(entity_uid);
     scene.remove_component::<Animation::<Sprite>>(entity_uid);
     scene.remove_component::<Animation::<Text>>(entity_uid);
     scene.remove_component::<PhysicsBody>(entity_uid);
     scene.remove_component::<PlayerInput>(entity_uid);
+    scene.remove_component::<Trigger>(entity_uid);
+    scene.remove_component::<Clickable>(entity_uid);
+    scene.remove_component::<PhysicsDebugDraw>(entity_uid);
+    scene.remove_component::<Platform>(entity_uid);
+    scene.remove_component::<DestinationMarker>(entity_uid);
+    scene.remove_component::<TextEntryDisplay>(entity_uid);
+    scene.remove_component::<PeerInterpolation>(entity_uid);
+    scene.remove_component::<PredictedMovement>(entity_uid);
+    scene.remove_component::<Nametag>(entity_uid);
+    scene.remove_component::<ChatBubble>(entity_uid);
 }
 
 //Runs every game tick. Updates all of the components, then renders all renderables that get batched.
 pub fn run_systems(scene: &mut Scene, render_state: &mut RenderState, input_state: &mut InputState, server_connection: &mut ServerConnection, delta_time : f32)
 {
     run_networking_system(scene, server_connection, render_state, delta_time); //TODO: remove render state 
-    run_input_system(scene, input_state); 
+    run_input_system(scene, input_state, render_state, delta_time); 
+    run_text_entry_system(scene, input_state, render_state, server_connection);
     run_physics_system(scene, delta_time);
+    run_prediction_system(scene, delta_time);
+    run_peer_interpolation_system(scene, server_connection);
+    run_trigger_system(scene, render_state);
     run_ai_system(scene, delta_time);
+    run_chat_system(scene, render_state, delta_time);
     run_animation_system(scene, delta_time);
     run_update_render_from_physics_system(scene, render_state);
     run_camera_update_system(scene, render_state);
+    run_destination_marker_system(scene, render_state);
+    run_physics_debug_draw_system(scene, render_state);
     run_render_system(scene, render_state); 
 }
 
@@ -320,22 +563,60 @@ fn load_batch_for_renderable_type<T: Renderable + Component>(scene: &mut Scene,
 
 fn run_networking_system(scene: &mut Scene, server_connection: &mut ServerConnection, render_state: &mut RenderState, delta_time: f32)
 {
+    server_connection.update(delta_time);
+
+    if server_connection.take_session_ended()
+    {
+        clear_peers(scene, render_state);
+    }
+
+    //Servers that reconcile need to know which of our ticks each update is as of
+    let mut sequence : Option<u32> = None;
+
+    if server_connection.has_capability(CAPABILITY_RECONCILIATION)
+    {
+        scene.apply_to_entities_with::<PredictedMovement, _>(|predicted_movement: &mut PredictedMovement|
+        {
+            sequence = Some(predicted_movement.get_sequence());
+        });
+    }
+
+    //So are servers that relay how we're moving and animated
+    let mut animation_state : Option<AnimationState> = None;
+
+    if server_connection.has_capability(CAPABILITY_MOTION_STATE)
+    {
+        scene.apply_to_entities_with_both::<PlayerInput, Animation<Sprite>, _>(|_player_input: &mut PlayerInput, animation: &mut Animation<Sprite>|
+        {
+            animation_state = Some(animation.get_animation_state().clone());
+        });
+    }
+
     scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
     {
-        server_connection.send_message_if_ready(&Message::new_update_message(physics_body.get_position().x,physics_body.get_position().y), delta_time);
+        let motion_state = animation_state.clone().map(|state|
+        {
+            let flags = if physics_body.get_ground_entity().is_some() { MOTION_FLAG_GROUNDED } else { 0 };
+            MotionState::new(*physics_body.get_velocity(), state, flags)
+        });
+
+        server_connection.send_update_if_needed(&Message::new_update_message(physics_body.get_position().x,physics_body.get_position().y,sequence,motion_state), delta_time);
     });
 
     let mut rng = rand::thread_rng();
 
+    //Everything received this tick is timestamped together
+    let now = server_connection.get_time();
+
     server_connection.receive_inbound_messages(&mut |message : &Message|
     {
         let mut entity_uid : Option<usize> = None;
 
         {
-            match message.uuid()
+            match message.peer_id()
             {
-                Some(u) => {
-                    match scene.get_entity_for_peer(u)
+                Some(p) => {
+                    match scene.get_entity_for_peer(&p)
                     {
                         Some(e) => {entity_uid = Some(*e);}
                         None => {}
@@ -345,17 +626,23 @@ fn run_networking_system(scene: &mut Scene, server_connection: &mut ServerConnec
             };
         }
 
+        let from_local_peer = match message.peer_id()
+        {
+            Some(p) => scene.is_local_peer(&p),
+            None => false
+        };
+
         match message.message_type()
         {
-            MessageType::Unknown=> {
-                log(&format!("Received a message of unknown type"));
+            MessageType::Hello | MessageType::Welcome | MessageType::Ping | MessageType::Pong => {
+                //Handshake and latency messages are handled by the server connection
             },
             MessageType::ChatMessage => {
 
                 log(&format!("Received a chat message "));
-                let uuid = match message.uuid()
+                let peer_id = match message.peer_id()
                 {
-                    Some(u) => u,
+                    Some(p) => p,
                     None => {return;}
                 };
 
@@ -368,28 +655,12 @@ fn run_networking_system(scene: &mut Scene, server_connection: &mut ServerConnec
                 match entity_uid
                 {
                     Some(euid) => {
-                        //Ew. This is expensive.
-
-                        /*
-                        let mut updatedText = "".to_string();
-                        scene.apply_to_entity::<Text, _>(euid,|component: &mut Text|
-                        {
-                            updatedText = component.get_content().clone();
-                        });
-
-                        updatedText.push_str(": ");
-                        updatedText.push_str(chat_message);
-                        */
-
-                        scene.remove_component::<Text>(euid);
-                        scene.add_component::<Text>(euid, Text::new_with_position(chat_message, &Font::Default, glm::vec2(0.0,150.0), 0.002, glm::vec2(1.0,1.0)));
+                        //Shown in place of the nametag until the bubble expires. A newer message restarts the timer.
+                        replace_text(scene, render_state, euid, chat_message);
+                        scene.remove_component::<ChatBubble>(euid);
+                        scene.add_component::<ChatBubble>(euid, ChatBubble::new(CHAT_BUBBLE_DURATION));
 
-                        scene.apply_to_entity::<Text, _>(euid,|component: &mut Text|
-                        {
-                            render_state.request_new_renderable::<Text>(component);
-                        });
-
-                        log(&format!("Peer {} ({}) says: {}",uuid,euid,chat_message));
+                        log(&format!("Peer {} ({}) says: {}",peer_id,euid,chat_message));
                     },
                     None => {
                         //A phantom message from no one we know about!
@@ -398,46 +669,44 @@ fn run_networking_system(scene: &mut Scene, server_connection: &mut ServerConnec
             },
             MessageType::Update => {
 
+                //Our own position echoed back - the player is already where it should be
+                if from_local_peer
+                {
+                    return;
+                }
+
                 let x_pos = message.x().unwrap_or(0.0);
                 let y_pos = message.y().unwrap_or(0.0);
 
                 match entity_uid
                 {
                     Some(euid) => {
-                        scene.apply_to_entity::<PhysicsBody, _>(euid, |physics_body : &mut PhysicsBody| 
+                        scene.apply_to_entity::<PeerInterpolation, _>(euid, |peer_interpolation: &mut PeerInterpolation|
                         {
-                            //Make peers walk to their current position
-                            let x = physics_body.get_position().x - x_pos;
-
-                            if x < 10.0 && x > -10.0
-                            {
-                                physics_body.set_velocity(0.0,0.0);
-                            } else if x > 10.0
-                            {
-                                physics_body.set_velocity(-1.0,0.0);
-                            } else
-                            {
-                                physics_body.set_velocity(1.0,0.0);
-                            }
+                            peer_interpolation.push_snapshot(now, glm::vec2(x_pos,y_pos), message.motion_state().cloned());
                         });
                     },
                     None => {
 
-                        let uuid = match message.uuid()
+                        let peer_id = match message.peer_id()
                         {
-                            Some(u) => u,
+                            Some(p) => p,
                             None => {return;}
                         };
 
-                        let peer_entity = match scene.add_entity_for_peer(uuid)
+                        let peer_entity = match scene.add_entity_for_peer(&peer_id)
                         {
                             Some(e) => e,
                             None => {return;}
                         };
 
-                        log(&format!("{} has arrived!",uuid));
+                        log(&format!("{} has arrived!",peer_id));
 
-                        scene.add_component::<PhysicsBody>(peer_entity, PhysicsBody::new());
+                        let mut peer_interpolation = PeerInterpolation::new();
+                        peer_interpolation.push_snapshot(now, glm::vec2(x_pos,y_pos), message.motion_state().cloned());
+
+                        scene.add_component::<PeerInterpolation>(peer_entity, peer_interpolation);
+                        scene.add_component::<PhysicsBody>(peer_entity, PhysicsBody::new_with_body_type(BodyType::Kinematic, glm::vec2(x_pos,y_pos), glm::vec2(290.0,90.0)));
                         scene.add_component::<Animation::<Sprite>>(peer_entity,Animation::<Sprite>::new(
                             HashMap::from([
                                 (AnimationState::FacingRight, vec![
@@ -517,20 +786,52 @@ fn run_networking_system(scene: &mut Scene, server_connection: &mut ServerConnec
                     }
                 }
             },
+            MessageType::Correction => {
+
+                //Corrections are only ever about us
+                if !from_local_peer
+                {
+                    return;
+                }
+
+                let acknowledged_sequence = match message.acknowledged_sequence()
+                {
+                    Some(s) => s,
+                    None => {return;}
+                };
+
+                let server_position = glm::vec2(message.x().unwrap_or(0.0), message.y().unwrap_or(0.0));
+
+                //Platforms are replayed against where they are now. Holding them still stops riders being carried once per replayed tick.
+                let platforms : HashMap<usize,PlatformState> = get_platforms(scene).into_iter()
+                    .map(|(uid, (position, _, size, platform_type))| (uid, (position, position, size, platform_type)))
+                    .collect();
+
+                scene.apply_to_entities_with_both::<PredictedMovement, PhysicsBody, _>(|predicted_movement: &mut PredictedMovement, physics_body: &mut PhysicsBody|
+                {
+                    let (velocity_y, ticks) = predicted_movement.reconcile(acknowledged_sequence);
+                    replay_predicted_movement(physics_body, &server_position, velocity_y, &ticks, &platforms);
+                });
+            },
             MessageType::Departure => {
 
-                let uuid = match message.uuid()
+                let peer_id = match message.peer_id()
                 {
-                    Some(u) => u,
+                    Some(p) => p,
                     None => {return;}
                 };
 
-                log(&format!("{} is departing...",uuid));
+                if from_local_peer
+                {
+                    return;
+                }
+
+                log(&format!("{} is departing...",peer_id));
 
                 if !entity_uid.is_none()
                 {
                     //Tell the scene to remove the entity from the map
-                    scene.remove_entity_for_peer(uuid);
+                    scene.remove_entity_for_peer(&peer_id);
 
                     //Also remove components. We have to do this separately for now because generics..
                     remove_entity(scene,render_state, entity_uid.unwrap());
@@ -538,148 +839,929 @@ fn run_networking_system(scene: &mut Scene, server_connection: &mut ServerConnec
             }
         };
     });
-}
-
-fn run_render_system(scene: &mut Scene, render_state: &mut RenderState)
-{   
-    render_state.clear_context();
-    render_state.submit_camera_uniforms(); 
-    render_state.bind_and_update_transform_buffer_data();
-
-    let mut sprite_batch = DrawBatch::<Sprite>::new();
-    let mut text_batch = DrawBatch::<Text>::new();
-
-    load_batch_for_renderable_type(scene,&mut sprite_batch); //Sprites
-    load_batch_for_renderable_type(scene, &mut text_batch); //Texts
 
-    //Render any entities that want to be drawn
-    render_state.draw(&mut sprite_batch);
-    render_state.draw(&mut text_batch);
+    link_local_peer(scene, server_connection, render_state);
 }
 
-fn run_input_system(scene: &mut Scene, input_state: &mut InputState)
+//Records the player's input this tick, so it can be simulated again when the server corrects them.
+//Runs after physics so that it can record how the tick ended.
+fn run_prediction_system(scene: &mut Scene, delta_time: f32)
 {
-    let mut velocity = glm::vec2(0.0,0.0);
+    let delta_time = delta_time.min(MAX_PHYSICS_STEP);
+    let mut inputs = Vec::new();
 
-    if input_state.get_current_mouse_location().is_active()
+    scene.apply_to_entities_with_uid::<PlayerInput, _>(|uid: usize, player_input: &mut PlayerInput|
     {
-        if *input_state.get_current_mouse_location().get_canvas_ratio_x() > 0.5
-        {
-            velocity.x = 1.0;
-        } else {
-            velocity.x = -1.0;
-        }
-    }
+        inputs.push((uid, *player_input.get_movement_input()));
+    });
 
-    let mut clicked = false;
-    while input_state.has_next_click()
+    for (entity_uid, movement_input) in inputs
     {
-        let click = match input_state.get_next_click()
-        {
+        let mut velocity_y = 0.0;
 
-            Some(c) => c,
-            None => { continue; }
-        };
+        scene.apply_to_entity::<PhysicsBody, _>(entity_uid, |physics_body: &mut PhysicsBody|
+        {
+            velocity_y = physics_body.get_velocity().y;
+        });
 
-        clicked = true;
-    }
-    
-    if clicked
-    {
-        //TODO
+        scene.apply_to_entity::<PredictedMovement, _>(entity_uid, |predicted_movement: &mut PredictedMovement|
+        {
+            predicted_movement.record(movement_input, delta_time, velocity_y);
+        });
     }
-
-    scene.apply_to_entities_with_both::<PhysicsBody, PlayerInput, _>(|physics_body: &mut PhysicsBody, _player_input: &mut PlayerInput|
-    {
-       physics_body.set_velocity(velocity.x,velocity.y);
-    });
-
 }
 
-fn run_physics_system(scene: &mut Scene,  delta_time: f32)
+//Puts a predicted body where the server says it was, then simulates the input the server hadn't seen yet on top.
+//Platforms are collided with along the way, so whatever the server stopped us at stops the replay too.
+fn replay_predicted_movement(physics_body: &mut PhysicsBody, server_position: &glm::Vec2, velocity_y: f32, ticks: &[PredictedTick], platforms: &HashMap<usize,PlatformState>)
 {
-    scene.apply_to_entities_with::<PhysicsBody, _>(|component: &mut PhysicsBody|
-    {
-        //Apply drag
+    physics_body.set_position(server_position.x, server_position.y);
+    physics_body.set_velocity(physics_body.get_velocity().x, velocity_y);
 
-        //position.y -= (delta_time / 5.0) * 10.0;
-        
-        //Change position based on velocity
-        //TODO: mass later?
-        let new_position = glm::vec2(component.get_position().x + (delta_time / 5.0) * component.get_velocity().x, component.get_position().y + (delta_time / 5.0) * component.get_velocity().y);
-        component.set_position(new_position.x,new_position.y);
-    });
+    for tick in ticks
+    {
+        apply_movement_input(physics_body, tick.get_input());
+        integrate_body(physics_body, tick.get_delta_time());
+        collide_with_platforms(physics_body, platforms);
+    }
 }
 
-fn run_ai_system(scene: &mut Scene, delta_time: f32)
+//Places remote peers where their buffered updates say they were, a little behind real time.
+//Runs after physics so that it has the final say on where peers are.
+fn run_peer_interpolation_system(scene: &mut Scene, server_connection: &ServerConnection)
 {
-    scene.apply_to_entities_with::<AI, _>(|component: &mut AI|
-    {
-        component.update(delta_time);
-    });
+    let now = server_connection.get_time();
+    let min_interpolation_delay = server_connection.get_interpolation_delay();
 
-    scene.apply_to_entities_with_both::<AI, PhysicsBody, _>(|ai: &mut AI, physics_body: &mut PhysicsBody|
+    scene.apply_to_entities_with_both::<PeerInterpolation, PhysicsBody, _>(|peer_interpolation: &mut PeerInterpolation, physics_body: &mut PhysicsBody|
     {
-        match ai.get_state()
+        let render_time = now - peer_interpolation.get_interpolation_delay().max(min_interpolation_delay);
+
+        let (position, mut velocity) = match peer_interpolation.sample(render_time)
         {
-            AIState::Idling => { physics_body.set_velocity(0.0,0.0); }
-            AIState::WalkingLeft => { physics_body.set_velocity(-1.0,0.0); }
-            AIState::WalkingRight => { physics_body.set_velocity(1.0,0.0); }
+            Some(s) => s,
+            None => { return; }
         };
+
+        //Better than working it out from positions, when the peer sends it
+        if let Some(motion_state) = peer_interpolation.get_motion_state()
+        {
+            velocity = *motion_state.get_velocity();
+        }
+
+        let previous_position = *physics_body.get_position();
+        physics_body.set_previous_position(previous_position.x, previous_position.y);
+        physics_body.set_position(position.x, position.y);
+
+        //Not used to move the peer, the position above is what counts
+        physics_body.set_velocity(velocity.x, velocity.y);
     });
 
-    //For now:
-    //Set the state of the animation based on the velocity direction
-    scene.apply_to_entities_with_both::<Animation<Sprite>, PhysicsBody, _>(|animation: &mut Animation<Sprite>, physics_body: &mut PhysicsBody|
+    //Peers that send their animation state are shown in it. Others are animated from their velocity like everything else.
+    scene.apply_to_entities_with_both::<PeerInterpolation, Animation<Sprite>, _>(|peer_interpolation: &mut PeerInterpolation, animation: &mut Animation<Sprite>|
     {
-        if physics_body.get_velocity().x == 0.0
+        let animation_state = match peer_interpolation.get_motion_state()
         {
-            match animation.get_animation_state()
-            {
-                AnimationState::FacingRight => {},
-                AnimationState::FacingLeft => {},
-                AnimationState::WalkingLeft => { animation.set_animation_state(AnimationState::FacingLeft)},
-                AnimationState::WalkingRight => { animation.set_animation_state(AnimationState::FacingRight)},
-            };
+            Some(m) => m.get_animation_state(),
+            None => { return; }
+        };
 
-            animation.set_animating(false);
-        } else if physics_body.get_velocity().x > 0.0
-        {
-            animation.set_animating(true);
-            animation.set_animation_state(AnimationState::WalkingRight);
-        } else
-        {
-            animation.set_animating(true);
-            animation.set_animation_state(AnimationState::WalkingLeft);
-        }
+        animation.set_animating(animation_state.is_walking());
+        animation.set_animation_state(animation_state);
     });
 }
 
-fn run_animation_system(scene: &mut Scene, delta_time: f32)
+//Removes everyone from the server we were on, and forgets who we were there
+fn clear_peers(scene: &mut Scene, render_state: &mut RenderState)
 {
-    scene.apply_to_entities_with::<Animation<Sprite>, _>(|component: &mut Animation<Sprite>|
+    for (peer_id, entity_uid) in scene.get_remote_peers()
     {
-        component.update(delta_time);
-    });
+        scene.remove_entity_for_peer(&peer_id);
+        remove_entity(scene, render_state, entity_uid);
+    }
 
-    scene.apply_to_entities_with::<Animation<Text>, _>(|component: &mut Animation<Text>|
+    let mut player_entity : Option<usize> = None;
+
+    scene.apply_to_entities_with_uid::<PlayerInput, _>(|uid: usize, _player_input: &mut PlayerInput|
     {
-        component.update(delta_time);
+        player_entity = Some(uid);
     });
-}
 
-fn run_chat_system(scene: &mut Scene, delta_time: f32)
-{
+    scene.clear_local_peer();
 
+    //The player's nametag (or last chat message) was from that server too
+    if let Some(player) = player_entity
+    {
+        remove_text(scene, render_state, player);
+        scene.remove_component::<Nametag>(player);
+        scene.remove_component::<ChatBubble>(player);
+    }
 }
 
-fn run_camera_update_system(scene: &mut Scene, render_state: &mut RenderState)
+//Once the server has told us who we are, map that id to the player so echoes of our own messages are recognised,
+//and label the player's possum with it
+fn link_local_peer(scene: &mut Scene, server_connection: &ServerConnection, render_state: &mut RenderState)
 {
-    scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+    let client_id = match server_connection.get_client_id()
     {
-        render_state.set_camera_world_position(physics_body.get_position());
-    });
-}
+        Some(id) => id,
+        None => { return; }
+    };
+
+    if scene.is_local_peer(client_id)
+    {
+        return;
+    }
+
+    let mut player_entity : Option<usize> = None;
+
+    scene.apply_to_entities_with_uid::<PlayerInput, _>(|uid: usize, _player_input: &mut PlayerInput|
+    {
+        player_entity = Some(uid);
+    });
+
+    let player = match player_entity
+    {
+        Some(p) => p,
+        None => { return; }
+    };
+
+    scene.set_local_peer(client_id, player);
+
+    log(&format!("We are {}", client_id));
+
+    let nametag = format!("you ({})", client_id.to_string().chars().take(8).collect::<String>());
+    scene.remove_component::<Nametag>(player);
+    scene.add_component::<Nametag>(player, Nametag::new(&nametag));
+
+    //A chat bubble that is still showing puts the nametag back once it expires
+    if !scene.has_component::<ChatBubble>(player)
+    {
+        replace_text(scene, render_state, player, &nametag);
+    }
+}
+
+fn run_render_system(scene: &mut Scene, render_state: &mut RenderState)
+{   
+    render_state.clear_context();
+    render_state.submit_camera_uniforms(); 
+    render_state.bind_and_update_transform_buffer_data();
+
+    let mut sprite_batch = DrawBatch::<Sprite>::new();
+    let mut text_batch = DrawBatch::<Text>::new();
+    let mut debug_lines_batch = DrawBatch::<DebugLines>::new();
+
+    load_batch_for_renderable_type(scene,&mut sprite_batch); //Sprites
+    load_batch_for_renderable_type(scene, &mut text_batch); //Texts
+
+    scene.apply_to_entities_with::<PhysicsDebugDraw, _>(|component: &mut PhysicsDebugDraw|
+    {
+        component.apply_to_renderables(|renderable: &mut DebugLines|
+        {
+            debug_lines_batch.add(&renderable.get_renderable_uid());
+        });
+    });
+
+    //Render any entities that want to be drawn
+    render_state.draw(&mut sprite_batch);
+    render_state.draw(&mut text_batch);
+    render_state.draw(&mut debug_lines_batch);
+}
+
+//Adds debug visuals to every PhysicsBody while physics debug drawing is enabled, and removes them when it is disabled
+fn run_physics_debug_draw_system(scene: &mut Scene, render_state: &mut RenderState)
+{
+    if !render_state.is_physics_debug_draw_enabled()
+    {
+        let mut entities_to_clean_up = Vec::<usize>::new();
+
+        scene.apply_to_entities_with_uid::<PhysicsDebugDraw, _>(|entity_uid: usize, component: &mut PhysicsDebugDraw|
+        {
+            component.apply_to_renderables(|renderable: &mut DebugLines|
+            {
+                render_state.free_renderable(renderable);
+            });
+
+            entities_to_clean_up.push(entity_uid);
+        });
+
+        for entity_uid in entities_to_clean_up
+        {
+            scene.remove_component::<PhysicsDebugDraw>(entity_uid);
+        }
+
+        return;
+    }
+
+    //Bodies can appear at any time (e.g. peers arriving), so check for new ones every tick
+    let mut bodies = Vec::<usize>::new();
+
+    scene.apply_to_entities_with_uid::<PhysicsBody, _>(|entity_uid: usize, _physics_body: &mut PhysicsBody|
+    {
+        bodies.push(entity_uid);
+    });
+
+    for entity_uid in bodies
+    {
+        if scene.has_component::<PhysicsDebugDraw>(entity_uid)
+        {
+            continue;
+        }
+
+        scene.add_component::<PhysicsDebugDraw>(entity_uid, PhysicsDebugDraw::new());
+
+        scene.apply_to_entity::<PhysicsDebugDraw, _>(entity_uid, |component: &mut PhysicsDebugDraw|
+        {
+            component.apply_to_renderables(|renderable: &mut DebugLines|
+            {
+                render_state.request_new_renderable::<DebugLines>(renderable);
+            });
+        });
+    }
+
+    scene.apply_to_entities_with_both::<PhysicsBody, PhysicsDebugDraw, _>(|physics_body: &mut PhysicsBody, component: &mut PhysicsDebugDraw|
+    {
+        let outline_uid = component.get_outline().get_renderable_uid();
+        render_state.set_position(&outline_uid, physics_body.get_position());
+        render_state.set_scale(&outline_uid, physics_body.get_size());
+
+        let velocity = physics_body.get_velocity();
+        let velocity_uid = component.get_velocity().get_renderable_uid();
+        render_state.set_position(&velocity_uid, physics_body.get_position());
+        render_state.set_rotation(&velocity_uid, velocity.y.atan2(velocity.x));
+        render_state.set_scale(&velocity_uid, &glm::vec2(glm::length(velocity) * VELOCITY_LINE_SCALE, 1.0));
+    });
+}
+
+fn run_input_system(scene: &mut Scene, input_state: &mut InputState, render_state: &mut RenderState, delta_time: f32)
+{
+    input_state.update(delta_time);
+
+    //Taps and swipes while typing are for reaching the chat field, not for moving the player.
+    //Both queues are still drained so nothing made while typing fires once text entry closes.
+    let typing = input_state.is_text_entry_active();
+
+    while let Some(gesture) = input_state.get_next_gesture()
+    {
+        if typing
+        {
+            continue;
+        }
+
+        match gesture
+        {
+            Gesture::Pinch { scale, center } => {
+                //Fingers moving apart means zooming in, i.e. fewer world units per pixel
+                let center_ratio = input_state.get_canvas_ratio(&center);
+                render_state.zoom_camera_towards(1.0 / scale, center_ratio.x, center_ratio.y);
+            },
+            Gesture::Tap | Gesture::LongPress | Gesture::Swipe => {}
+        };
+    }
+
+    //Scrolling zooms towards the cursor, the zoom keys towards the middle of the screen
+    let scroll_delta = input_state.get_snapshot().get_scroll_delta().y;
+
+    if scroll_delta != 0.0
+    {
+        let cursor = input_state.get_current_mouse_location();
+        render_state.zoom_camera_towards((scroll_delta * SCROLL_ZOOM_SPEED).exp2(), *cursor.get_canvas_ratio_x(), *cursor.get_canvas_ratio_y());
+    }
+
+    if input_state.get_snapshot().is_action_just_pressed(Action::ZoomIn)
+    {
+        render_state.zoom_camera_towards(0.5, 0.5, 0.5);
+    }
+
+    if input_state.get_snapshot().is_action_just_pressed(Action::ZoomOut)
+    {
+        render_state.zoom_camera_towards(2.0, 0.5, 0.5);
+    }
+
+    //Keyboard (or any other input bound to movement) takes priority over walking to a clicked destination
+    let movement_axis = input_state.get_action_axis(Action::MoveLeft, Action::MoveRight);
+    let jumping = input_state.get_snapshot().is_action_just_pressed(Action::Jump);
+
+    let mut click_events = Vec::new();
+    let mut new_destination : Option<glm::Vec2> = None;
+
+    while input_state.has_next_click()
+    {
+        let click = match input_state.get_next_click()
+        {
+
+            Some(c) => c,
+            None => { continue; }
+        };
+
+        if typing
+        {
+            continue;
+        }
+
+        let world_position = render_state.canvas_ratio_to_world_position(*click.get_canvas_ratio_x(), *click.get_canvas_ratio_y());
+
+        //Clicking something clickable interacts with it, clicking anywhere else walks there
+        match pick_entity(scene, render_state, &world_position)
+        {
+            Some(picked) => { click_events.push(picked); },
+            None => { new_destination = Some(world_position); }
+        };
+    }
+
+    //Callbacks run after picking so that they are free to modify the scene
+    for (callback, event) in click_events
+    {
+        callback(scene, render_state, &event);
+    }
+
+    scene.apply_to_entities_with_both::<PhysicsBody, PlayerInput, _>(|physics_body: &mut PhysicsBody, player_input: &mut PlayerInput|
+    {
+        if new_destination.is_some()
+        {
+            player_input.set_destination(new_destination);
+        }
+
+        let mut velocity_x = 0.0;
+
+        if movement_axis != 0.0
+        {
+            player_input.set_destination(None);
+            velocity_x = movement_axis;
+        } else if let Some(destination) = player_input.get_destination()
+        {
+            let x_distance = destination.x - physics_body.get_position().x;
+
+            if x_distance.abs() <= DESTINATION_ARRIVAL_DISTANCE
+            {
+                player_input.set_destination(None);
+            } else
+            {
+                velocity_x = x_distance.signum();
+            }
+        }
+
+        let movement_input = MovementInput { velocity_x, jumped: jumping && physics_body.is_grounded() };
+        apply_movement_input(physics_body, &movement_input);
+        player_input.set_movement_input(movement_input);
+    });
+}
+
+fn apply_movement_input(physics_body: &mut PhysicsBody, movement_input: &MovementInput)
+{
+    physics_body.set_velocity_x(movement_input.velocity_x);
+
+    if movement_input.jumped
+    {
+        physics_body.set_velocity(movement_input.velocity_x, JUMP_SPEED);
+    }
+}
+
+//Opens text entry on the chat action, sends finished lines as chat messages and shows the line being typed above the player
+fn run_text_entry_system(scene: &mut Scene, input_state: &mut InputState, render_state: &mut RenderState, server_connection: &mut ServerConnection)
+{
+    if !input_state.is_text_entry_active() && input_state.get_snapshot().is_action_just_pressed(Action::Chat)
+    {
+        input_state.start_text_entry();
+    }
+
+    while let Some(line) = input_state.get_next_submitted_text()
+    {
+        server_connection.immediately_send_message(&Message::new_chat_message(line));
+    }
+
+    let mut player_position = glm::vec2(0.0,0.0);
+
+    scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+    {
+        player_position = *physics_body.get_position();
+    });
+
+    let display_position = player_position + glm::vec2(0.0, TEXT_ENTRY_OFFSET_Y);
+
+    let mut display_entities = Vec::new();
+
+    scene.apply_to_entities_with_uid::<TextEntryDisplay, _>(|uid: usize, _display: &mut TextEntryDisplay|
+    {
+        display_entities.push(uid);
+    });
+
+    let changed = input_state.take_text_entry_changed();
+
+    for display in display_entities
+    {
+        //Text content can't be changed in place, so replace it whenever the line changes
+        if changed && scene.has_component::<Text>(display)
+        {
+            scene.apply_to_entity::<Text, _>(display,|component: &mut Text|
+            {
+                render_state.free_renderable(component);
+            });
+
+            scene.remove_component::<Text>(display);
+        }
+
+        if changed
+        {
+            if let Some(line) = input_state.get_text_entry()
+            {
+                let content = format!("> {}_", line);
+
+                scene.add_component::<Text>(display, Text::new_with_position(&content, &Font::Default, display_position, 0.003, glm::vec2(1.0,1.0)));
+
+                scene.apply_to_entity::<Text, _>(display,|component: &mut Text|
+                {
+                    render_state.request_new_renderable::<Text>(component);
+                });
+            }
+        }
+
+        scene.apply_to_entity::<Text, _>(display,|component: &mut Text|
+        {
+            render_state.set_position(&component.get_renderable_uid(), &display_position);
+        });
+    }
+}
+
+//Shows the destination marker where the player is walking to, or hides it if they aren't walking anywhere
+fn run_destination_marker_system(scene: &mut Scene, render_state: &mut RenderState)
+{
+    let mut destination : Option<glm::Vec2> = None;
+
+    scene.apply_to_entities_with::<PlayerInput, _>(|player_input: &mut PlayerInput|
+    {
+        destination = *player_input.get_destination();
+    });
+
+    scene.apply_to_entities_with_both::<DestinationMarker, Sprite, _>(|_marker: &mut DestinationMarker, sprite: &mut Sprite|
+    {
+        let uid = sprite.get_renderable_uid();
+
+        match destination
+        {
+            Some(d) => {
+                render_state.set_position(&uid, &d);
+                render_state.set_scale(&uid, &glm::vec2(0.5,0.5));
+            },
+            None => {
+                render_state.set_scale(&uid, &glm::vec2(0.0,0.0));
+            }
+        };
+    });
+}
+
+//Find the topmost clickable entity whose sprite contains the world position
+fn pick_entity(scene: &mut Scene, render_state: &RenderState, world_position: &glm::Vec2) -> Option<(ClickCallback, ClickEvent)>
+{
+    let mut clickables = HashMap::<usize,ClickCallback>::new();
+
+    scene.apply_to_entities_with_uid::<Clickable, _>(|entity_uid: usize, clickable: &mut Clickable|
+    {
+        clickables.insert(entity_uid, clickable.get_on_click());
+    });
+
+    if clickables.is_empty()
+    {
+        return None;
+    }
+
+    //(entity uid, z) of the best candidate so far
+    let mut topmost : Option<(usize,f32)> = None;
+
+    let mut hit_test = |entity_uid: usize, renderable: &Sprite|
+    {
+        if !clickables.contains_key(&entity_uid)
+        {
+            return;
+        }
+
+        let (position, size, z) = match render_state.get_world_bounds(&renderable.get_renderable_uid(), renderable.get_size())
+        {
+            Some(b) => b,
+            None => { return; }
+        };
+
+        if !rectangles_overlap(&position, &size, world_position, &glm::vec2(0.0,0.0))
+        {
+            return;
+        }
+
+        match topmost
+        {
+            Some((_, topmost_z)) if topmost_z >= z => {},
+            _ => { topmost = Some((entity_uid, z)); }
+        };
+    };
+
+    scene.apply_to_entities_with_uid::<Sprite, _>(|entity_uid: usize, renderable: &mut Sprite|
+    {
+        hit_test(entity_uid, renderable);
+    });
+
+    scene.apply_to_entities_with_uid::<Animation<Sprite>, _>(|entity_uid: usize, animation: &mut Animation<Sprite>|
+    {
+        match animation.get_current_renderable()
+        {
+            Some(r) => { hit_test(entity_uid, r); },
+            None => {}
+        };
+    });
+
+    let (entity_uid, _) = topmost?;
+
+    Some((clickables[&entity_uid], ClickEvent::new(entity_uid, *world_position)))
+}
+
+fn run_physics_system(scene: &mut Scene,  delta_time: f32)
+{
+    let delta_time = delta_time.min(MAX_PHYSICS_STEP);
+
+    //Moving platforms head for their next waypoint
+    scene.apply_to_entities_with_both::<Platform, PhysicsBody, _>(|platform: &mut Platform, physics_body: &mut PhysicsBody|
+    {
+        let velocity = platform.get_velocity_towards_next_waypoint(physics_body.get_position(), delta_time);
+        physics_body.set_velocity(velocity.x, velocity.y);
+    });
+
+    scene.apply_to_entities_with::<PhysicsBody, _>(|component: &mut PhysicsBody|
+    {
+        integrate_body(component, delta_time);
+    });
+
+    resolve_platform_collisions(scene);
+    resolve_body_overlaps(scene);
+}
+
+fn integrate_body(component: &mut PhysicsBody, delta_time: f32)
+{
+    let position = *component.get_position();
+    component.set_previous_position(position.x, position.y);
+
+    if *component.get_body_type() == BodyType::Static
+    {
+        return;
+    }
+
+    //Apply gravity
+    if *component.get_body_type() == BodyType::Dynamic
+    {
+        let velocity = *component.get_velocity();
+        component.set_velocity(velocity.x, velocity.y - GRAVITY * delta_time);
+    }
+    
+    //Change position based on velocity
+    //TODO: mass later?
+    let new_position = glm::vec2(component.get_position().x + (delta_time / 5.0) * component.get_velocity().x, component.get_position().y + (delta_time / 5.0) * component.get_velocity().y);
+    component.set_position(new_position.x,new_position.y);
+}
+
+//(position, previous position, size, type) of a platform
+type PlatformState = (glm::Vec2,glm::Vec2,glm::Vec2,PlatformType);
+
+fn get_platforms(scene: &mut Scene) -> HashMap<usize,PlatformState>
+{
+    let mut platforms = HashMap::<usize,PlatformState>::new();
+
+    scene.apply_to_entities_with_uid::<Platform, _>(|entity_uid: usize, platform: &mut Platform|
+    {
+        platforms.insert(entity_uid, (glm::vec2(0.0,0.0), glm::vec2(0.0,0.0), glm::vec2(0.0,0.0), *platform.get_platform_type()));
+    });
+
+    if platforms.is_empty()
+    {
+        return platforms;
+    }
+
+    scene.apply_to_entities_with_uid::<PhysicsBody, _>(|entity_uid: usize, physics_body: &mut PhysicsBody|
+    {
+        match platforms.get_mut(&entity_uid)
+        {
+            Some(p) => {
+                p.0 = *physics_body.get_position();
+                p.1 = *physics_body.get_previous_position();
+                p.2 = *physics_body.get_size();
+            },
+            None => {}
+        };
+    });
+
+    platforms
+}
+
+//Carry riders along with the platform they stand on, then keep dynamic bodies out of (or on top of) platforms
+fn resolve_platform_collisions(scene: &mut Scene)
+{
+    let platforms = get_platforms(scene);
+
+    if platforms.is_empty()
+    {
+        return;
+    }
+
+    scene.apply_to_entities_with_uid::<PhysicsBody, _>(|entity_uid: usize, physics_body: &mut PhysicsBody|
+    {
+        if *physics_body.get_body_type() != BodyType::Dynamic || platforms.contains_key(&entity_uid)
+        {
+            return;
+        }
+
+        collide_with_platforms(physics_body, &platforms);
+    });
+}
+
+fn collide_with_platforms(physics_body: &mut PhysicsBody, platforms: &HashMap<usize,PlatformState>)
+{
+    let mut position = *physics_body.get_position();
+    let mut velocity = *physics_body.get_velocity();
+    let size = *physics_body.get_size();
+
+    //Riders move with whatever they were standing on last tick
+    match physics_body.get_ground_entity().and_then(|ground| platforms.get(&ground))
+    {
+        Some((platform_position, platform_previous_position, _, _)) => {
+            position += platform_position - platform_previous_position;
+        },
+        None => {}
+    };
+
+    let previous_bottom = physics_body.get_previous_position().y - size.y * 0.5;
+    let mut ground_entity = None;
+
+    for (platform_uid, (platform_position, platform_previous_position, platform_size, platform_type)) in platforms
+    {
+        if !rectangles_overlap(&position, &size, platform_position, platform_size)
+        {
+            continue;
+        }
+
+        let platform_top = platform_position.y + platform_size.y * 0.5;
+        let x_penetration = (size.x + platform_size.x) * 0.5 - (position.x - platform_position.x).abs();
+        let y_penetration = (size.y + platform_size.y) * 0.5 - (position.y - platform_position.y).abs();
+
+        match platform_type
+        {
+            PlatformType::OneWay => {
+                //Only land if we were above the platform last tick and are now falling onto it
+                let previous_platform_top = platform_previous_position.y + platform_size.y * 0.5;
+
+                if velocity.y > 0.0 || previous_bottom < previous_platform_top - 0.01
+                {
+                    continue;
+                }
+
+                position.y = platform_top + size.y * 0.5;
+                velocity.y = 0.0;
+                ground_entity = Some(*platform_uid);
+            },
+            PlatformType::Solid => {
+                //Push out along whichever axis needs the smaller correction
+                if x_penetration < y_penetration
+                {
+                    position.x += if position.x < platform_position.x { -x_penetration } else { x_penetration };
+                } else if position.y >= platform_position.y
+                {
+                    position.y = platform_top + size.y * 0.5;
+                    velocity.y = velocity.y.max(0.0);
+                    ground_entity = Some(*platform_uid);
+                } else
+                {
+                    position.y = platform_position.y - platform_size.y * 0.5 - size.y * 0.5;
+                    velocity.y = velocity.y.min(0.0);
+                }
+            }
+        };
+    }
+
+    physics_body.set_position(position.x, position.y);
+    physics_body.set_velocity(velocity.x, velocity.y);
+    physics_body.set_ground_entity(ground_entity);
+}
+
+//Push dynamic bodies out of anything they overlap. Possums only shove each other sideways.
+fn resolve_body_overlaps(scene: &mut Scene)
+{
+    let mut platforms = HashSet::<usize>::new();
+
+    scene.apply_to_entities_with_uid::<Platform, _>(|entity_uid: usize, _platform: &mut Platform|
+    {
+        platforms.insert(entity_uid);
+    });
+
+    let mut npcs = HashSet::<usize>::new();
+
+    scene.apply_to_entities_with_uid::<AI, _>(|entity_uid: usize, _ai: &mut AI|
+    {
+        npcs.insert(entity_uid);
+    });
+
+    let mut bodies = Vec::<(usize,glm::Vec2,glm::Vec2,BodyType)>::new();
+
+    //Platforms are handled separately, they don't shove
+    scene.apply_to_entities_with_uid::<PhysicsBody, _>(|entity_uid: usize, physics_body: &mut PhysicsBody|
+    {
+        if platforms.contains(&entity_uid)
+        {
+            return;
+        }
+
+        bodies.push((entity_uid, *physics_body.get_position(), *physics_body.get_size(), *physics_body.get_body_type()));
+    });
+
+    let mut corrections = HashMap::<usize,f32>::new();
+
+    for a in 0..bodies.len()
+    {
+        for b in (a + 1)..bodies.len()
+        {
+            let (a_uid, a_position, a_size, a_type) = &bodies[a];
+            let (b_uid, b_position, b_size, b_type) = &bodies[b];
+
+            let a_share = a_type.get_push_share(b_type);
+            let b_share = b_type.get_push_share(a_type);
+
+            if a_share == 0.0 && b_share == 0.0
+            {
+                continue;
+            }
+
+            //NPCs wander past each other rather than jostling
+            if npcs.contains(a_uid) && npcs.contains(b_uid)
+            {
+                continue;
+            }
+
+            if !rectangles_overlap(a_position, a_size, b_position, b_size)
+            {
+                continue;
+            }
+
+            let x_distance = a_position.x - b_position.x;
+            let penetration = (a_size.x + b_size.x) * 0.5 - x_distance.abs();
+            let direction = if x_distance < 0.0 { -1.0 } else { 1.0 };
+
+            *corrections.entry(*a_uid).or_insert(0.0) += penetration * a_share * direction;
+            *corrections.entry(*b_uid).or_insert(0.0) -= penetration * b_share * direction;
+        }
+    }
+
+    for (entity_uid, correction) in corrections
+    {
+        scene.apply_to_entity::<PhysicsBody, _>(entity_uid, |physics_body: &mut PhysicsBody|
+        {
+            let position = *physics_body.get_position();
+            physics_body.set_position(position.x + correction, position.y);
+        });
+    }
+}
+
+fn run_trigger_system(scene: &mut Scene, render_state: &mut RenderState)
+{
+    let mut bodies = Vec::<(usize,glm::Vec2,glm::Vec2)>::new();
+
+    scene.apply_to_entities_with_uid::<PhysicsBody, _>(|entity_uid: usize, physics_body: &mut PhysicsBody|
+    {
+        bodies.push((entity_uid, *physics_body.get_position(), *physics_body.get_size()));
+    });
+
+    let mut events = Vec::new();
+
+    scene.apply_to_entities_with_uid::<Trigger, _>(|entity_uid: usize, trigger: &mut Trigger|
+    {
+        events.extend(trigger.update(entity_uid, &bodies));
+    });
+
+    //Callbacks run once the trigger buffer is released, so they are free to modify the scene
+    for (callback, event) in events
+    {
+        callback(scene, render_state, &event);
+    }
+}
+
+fn run_ai_system(scene: &mut Scene, delta_time: f32)
+{
+    scene.apply_to_entities_with::<AI, _>(|component: &mut AI|
+    {
+        component.update(delta_time);
+    });
+
+    scene.apply_to_entities_with_both::<AI, PhysicsBody, _>(|ai: &mut AI, physics_body: &mut PhysicsBody|
+    {
+        match ai.get_state()
+        {
+            AIState::Idling => { physics_body.set_velocity_x(0.0); }
+            AIState::WalkingLeft => { physics_body.set_velocity_x(-1.0); }
+            AIState::WalkingRight => { physics_body.set_velocity_x(1.0); }
+        };
+    });
+
+    //Peers that send their animation state have already been set up by the peer interpolation system
+    let mut animated_by_peer = HashSet::<usize>::new();
+
+    scene.apply_to_entities_with_uid::<PeerInterpolation, _>(|entity_uid: usize, peer_interpolation: &mut PeerInterpolation|
+    {
+        if peer_interpolation.get_motion_state().is_some()
+        {
+            animated_by_peer.insert(entity_uid);
+        }
+    });
+
+    let mut velocities = HashMap::<usize,glm::Vec2>::new();
+
+    scene.apply_to_entities_with_uid::<PhysicsBody, _>(|entity_uid: usize, physics_body: &mut PhysicsBody|
+    {
+        if !animated_by_peer.contains(&entity_uid)
+        {
+            velocities.insert(entity_uid, *physics_body.get_velocity());
+        }
+    });
+
+    //For now:
+    //Set the state of the animation based on the velocity direction
+    scene.apply_to_entities_with_uid::<Animation<Sprite>, _>(|entity_uid: usize, animation: &mut Animation<Sprite>|
+    {
+        let velocity = match velocities.get(&entity_uid)
+        {
+            Some(v) => v,
+            None => { return; }
+        };
+
+        if velocity.x == 0.0
+        {
+            match animation.get_animation_state()
+            {
+                AnimationState::FacingRight => {},
+                AnimationState::FacingLeft => {},
+                AnimationState::WalkingLeft => { animation.set_animation_state(AnimationState::FacingLeft)},
+                AnimationState::WalkingRight => { animation.set_animation_state(AnimationState::FacingRight)},
+            };
+
+            animation.set_animating(false);
+        } else if velocity.x > 0.0
+        {
+            animation.set_animating(true);
+            animation.set_animation_state(AnimationState::WalkingRight);
+        } else
+        {
+            animation.set_animating(true);
+            animation.set_animation_state(AnimationState::WalkingLeft);
+        }
+    });
+}
+
+fn run_animation_system(scene: &mut Scene, delta_time: f32)
+{
+    scene.apply_to_entities_with::<Animation<Sprite>, _>(|component: &mut Animation<Sprite>|
+    {
+        component.update(delta_time);
+    });
+
+    scene.apply_to_entities_with::<Animation<Text>, _>(|component: &mut Animation<Text>|
+    {
+        component.update(delta_time);
+    });
+}
+
+//Puts entities' nametags back (or clears their Text) once what they said has been up for long enough
+fn run_chat_system(scene: &mut Scene, render_state: &mut RenderState, delta_time: f32)
+{
+    let mut expired = Vec::new();
+
+    scene.apply_to_entities_with_uid::<ChatBubble, _>(|uid: usize, chat_bubble: &mut ChatBubble|
+    {
+        chat_bubble.update(delta_time);
+
+        if chat_bubble.is_expired()
+        {
+            expired.push(uid);
+        }
+    });
+
+    for entity_uid in expired
+    {
+        scene.remove_component::<ChatBubble>(entity_uid);
+
+        let mut nametag : Option<String> = None;
+
+        scene.apply_to_entity::<Nametag, _>(entity_uid, |component: &mut Nametag|
+        {
+            nametag = Some(component.get_content().clone());
+        });
+
+        match nametag
+        {
+            Some(n) => replace_text(scene, render_state, entity_uid, &n),
+            None => remove_text(scene, render_state, entity_uid)
+        };
+    }
+}
+
+fn run_camera_update_system(scene: &mut Scene, render_state: &mut RenderState)
+{
+    scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+    {
+        render_state.set_camera_world_position(physics_body.get_position());
+    });
+}
 
 fn run_update_render_from_physics_system(scene: &mut Scene, render_state: &mut RenderState)
 {
@@ -719,3 +1801,481 @@ fn run_update_render_from_physics_system(scene: &mut Scene, render_state: &mut R
         }
     });
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::networking::loopback_transport::LoopbackServer;
+    use crate::networking::server_connection::ConnectionState;
+    use crate::networking::peer_id::PeerId;
+    use std::convert::TryInto;
+
+    static LOCAL_UUID : &str = "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaa";
+    static PEER_UUID : &str = "bbbbbbbb-bbbb-bbbb-bbbb-bbbbbbbbbbbb";
+
+    struct Harness
+    {
+        scene: Scene,
+        render_state: RenderState,
+        server_connection: ServerConnection,
+        server: LoopbackServer
+    }
+
+    impl Harness
+    {
+        fn new() -> Self
+        {
+            let server = LoopbackServer::new();
+            let mut server_connection = ServerConnection::new_with_transport_factory(server.factory());
+            server_connection.connect("loopback");
+
+            let mut scene = Scene::new();
+            let player = scene.add_entity().unwrap();
+            scene.add_component::<PlayerInput>(player, PlayerInput::new());
+            scene.add_component::<PredictedMovement>(player, PredictedMovement::new());
+            scene.add_component::<PhysicsBody>(player, PhysicsBody::new_with_body_type(BodyType::Dynamic, glm::vec2(0.0,0.0), glm::vec2(290.0,90.0)));
+
+            Self
+            {
+                scene,
+                render_state: RenderState::new_headless(800, 600),
+                server_connection,
+                server
+            }
+        }
+
+        fn tick(&mut self, delta_time: f32)
+        {
+            run_networking_system(&mut self.scene, &mut self.server_connection, &mut self.render_state, delta_time);
+        }
+
+        fn handshake(&mut self)
+        {
+            self.server.accept();
+            self.tick(16.0);
+            self.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 1u32.to_le_bytes()].concat()));
+            self.tick(16.0);
+        }
+    }
+
+    fn peer_id(uuid: &str) -> PeerId
+    {
+        PeerId::parse(uuid).unwrap()
+    }
+
+    //Inbound messages are 4 (type) + 36 (uuid) + payload, as sent by a protocol v1 server
+    fn server_message(message_type: i32, uuid: &str, payload: &[u8]) -> Vec<u8>
+    {
+        [&message_type.to_le_bytes()[..], uuid.as_bytes(), payload].concat()
+    }
+
+    //From v2 the uuid is 16 bytes instead
+    fn binary_server_message(message_type: i32, uuid: &str, payload: &[u8]) -> Vec<u8>
+    {
+        [&message_type.to_le_bytes()[..], &peer_id(uuid).as_bytes()[..], payload].concat()
+    }
+
+    fn update_message(uuid: &str, x: f32, y: f32) -> Vec<u8>
+    {
+        server_message(1, uuid, &[x.to_le_bytes(), y.to_le_bytes()].concat())
+    }
+
+    fn sent_message_types(server: &LoopbackServer) -> Vec<i32>
+    {
+        server.take_sent().iter().map(|bytes| i32::from_le_bytes(bytes[0..4].try_into().unwrap())).collect()
+    }
+
+    #[test]
+    fn hello_is_sent_once_open_and_welcome_links_the_player()
+    {
+        let mut harness = Harness::new();
+
+        harness.tick(16.0);
+        assert!(harness.server.take_sent().is_empty());
+
+        harness.server.accept();
+        harness.tick(16.0);
+        assert_eq!(sent_message_types(&harness.server), vec![4]);
+
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 1u32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+
+        assert!(harness.scene.is_local_peer(&peer_id(LOCAL_UUID)));
+        assert_eq!(harness.server_connection.get_client_id(), &Some(peer_id(LOCAL_UUID)));
+        assert!(harness.server_connection.get_state() == ConnectionState::Open);
+    }
+
+    #[test]
+    fn peers_come_and_go_and_our_own_echoes_are_ignored()
+    {
+        let mut harness = Harness::new();
+        harness.handshake();
+
+        harness.server.send_to_client(update_message(PEER_UUID, 100.0, 0.0));
+        harness.server.send_to_client(update_message(LOCAL_UUID, 50.0, 0.0));
+        harness.tick(16.0);
+
+        let peers = harness.scene.get_remote_peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].0, peer_id(PEER_UUID));
+
+        harness.server.send_to_client(server_message(2, LOCAL_UUID, &[]));
+        harness.server.send_to_client(server_message(2, PEER_UUID, &[]));
+        harness.tick(16.0);
+
+        assert!(harness.scene.get_remote_peers().is_empty());
+        assert!(harness.scene.is_local_peer(&peer_id(LOCAL_UUID)));
+    }
+
+    #[test]
+    fn v2_servers_identify_peers_in_binary()
+    {
+        let mut harness = Harness::new();
+
+        harness.server.accept();
+        harness.tick(16.0);
+        harness.server.send_to_client(binary_server_message(5, LOCAL_UUID, &[2u32.to_le_bytes(), 1u32.to_le_bytes()].concat()));
+        harness.server.send_to_client(binary_server_message(1, PEER_UUID, &[100.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat()));
+        harness.server.send_to_client(binary_server_message(1, LOCAL_UUID, &[50.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+
+        assert!(harness.scene.is_local_peer(&peer_id(LOCAL_UUID)));
+
+        let peers = harness.scene.get_remote_peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].0, peer_id(PEER_UUID));
+
+        harness.server.send_to_client(binary_server_message(2, PEER_UUID, &[]));
+        harness.tick(16.0);
+
+        assert!(harness.scene.get_remote_peers().is_empty());
+    }
+
+    fn player_text(scene: &mut Scene) -> Option<String>
+    {
+        let mut content = None;
+
+        scene.apply_to_entities_with_both::<PlayerInput, Text, _>(|_player_input: &mut PlayerInput, text: &mut Text|
+        {
+            content = Some(text.get_content().clone());
+        });
+
+        content
+    }
+
+    #[test]
+    fn our_own_chat_shows_in_place_of_the_nametag_until_it_expires()
+    {
+        let mut harness = Harness::new();
+        harness.handshake();
+        assert_eq!(player_text(&mut harness.scene), Some("you (aaaaaaaa)".to_string()));
+
+        harness.server.send_to_client(server_message(3, LOCAL_UUID, b"hello"));
+        harness.tick(16.0);
+        assert_eq!(player_text(&mut harness.scene), Some("hello".to_string()));
+
+        run_chat_system(&mut harness.scene, &mut harness.render_state, CHAT_BUBBLE_DURATION - 1.0);
+        assert_eq!(player_text(&mut harness.scene), Some("hello".to_string()));
+
+        run_chat_system(&mut harness.scene, &mut harness.render_state, 1.0);
+        assert_eq!(player_text(&mut harness.scene), Some("you (aaaaaaaa)".to_string()));
+    }
+
+    #[test]
+    fn a_long_frame_does_not_drop_the_player_through_the_ground()
+    {
+        let mut harness = Harness::new();
+        add_platform(&mut harness.scene, Platform::new(PlatformType::Solid), BodyType::Static, glm::vec2(0.0,-95.0), glm::vec2(10000.0,100.0), None);
+
+        for delta_time in [16.0, 5000.0, 16.0]
+        {
+            run_physics_system(&mut harness.scene, delta_time);
+        }
+
+        let mut player_y = 0.0;
+        harness.scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+        {
+            player_y = physics_body.get_position().y;
+        });
+
+        assert!(player_y > -1.0, "player fell to {}", player_y);
+    }
+
+    #[test]
+    fn updates_are_only_sent_after_the_handshake()
+    {
+        let mut harness = Harness::new();
+
+        harness.server.accept();
+        harness.tick(100.0);
+        assert_eq!(sent_message_types(&harness.server), vec![4]);
+
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 1u32.to_le_bytes()].concat()));
+        harness.tick(100.0);
+        harness.tick(100.0);
+
+        assert_eq!(sent_message_types(&harness.server), vec![1]);
+    }
+
+    #[test]
+    fn corrections_replay_unacknowledged_movement()
+    {
+        let mut harness = Harness::new();
+        harness.server.accept();
+        harness.tick(16.0);
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 3u32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+        harness.server.take_sent();
+
+        //Four ticks of moving right one unit a tick
+        for _ in 0..4
+        {
+            predict_walking(&mut harness.scene, 1.0, 5.0);
+        }
+
+        //Updates carry the latest tick
+        harness.tick(100.0);
+        let sent = harness.server.take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(u32::from_le_bytes(sent[0][12..16].try_into().unwrap()), 4);
+
+        //Server only got as far as tick 2, and had us blocked at x = 1 by then
+        harness.server.send_to_client(server_message(6, LOCAL_UUID, &[2u32.to_le_bytes(), 1.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+
+        let mut x = 0.0;
+        harness.scene.apply_to_entities_with::<PhysicsBody, _>(|physics_body: &mut PhysicsBody|
+        {
+            x = physics_body.get_position().x;
+        });
+
+        assert_eq!(x, 3.0);
+    }
+
+    #[test]
+    fn corrections_replay_into_walls()
+    {
+        let mut harness = Harness::new();
+        harness.server.accept();
+        harness.tick(16.0);
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 3u32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+
+        //The player's right edge is 145 from its middle, so this wall is 50 units to the right of a player at x = 0
+        add_platform(&mut harness.scene, Platform::new(PlatformType::Solid), BodyType::Static, glm::vec2(205.0,0.0), glm::vec2(20.0,10000.0), None);
+
+        //Four ticks of moving right ten units a tick, stopping short of the wall at x = 40
+        for _ in 0..4
+        {
+            predict_walking(&mut harness.scene, 1.0, 50.0);
+        }
+
+        //Server had us shoved further right by tick 2, so the two ticks after it walk into the wall rather than through it
+        harness.server.send_to_client(server_message(6, LOCAL_UUID, &[2u32.to_le_bytes(), 45.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+
+        let mut x = 0.0;
+        harness.scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+        {
+            x = physics_body.get_position().x;
+        });
+
+        assert_eq!(x, 50.0);
+    }
+
+    //Simulates and records one tick of the player walking, as the input system would
+    fn predict_walking(scene: &mut Scene, velocity_x: f32, delta_time: f32)
+    {
+        scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+        {
+            let movement_input = MovementInput { velocity_x, jumped: false };
+            apply_movement_input(physics_body, &movement_input);
+            player_input.set_movement_input(movement_input);
+        });
+
+        run_physics_system(scene, delta_time);
+        run_prediction_system(scene, delta_time);
+    }
+
+    fn move_player(scene: &mut Scene, x: f32)
+    {
+        scene.apply_to_entities_with_both::<PlayerInput, PhysicsBody, _>(|_player_input: &mut PlayerInput, physics_body: &mut PhysicsBody|
+        {
+            physics_body.set_position(x, 0.0);
+        });
+    }
+
+    #[test]
+    fn updates_are_sent_on_change_with_a_slow_keepalive()
+    {
+        let mut harness = Harness::new();
+        harness.handshake();
+        harness.server.take_sent();
+
+        harness.tick(50.0);
+        assert_eq!(sent_message_types(&harness.server), vec![1]);
+
+        //Standing still
+        for _ in 0..10
+        {
+            harness.tick(50.0);
+        }
+        assert!(harness.server.take_sent().is_empty());
+
+        //Moving, even less than the keepalive interval later
+        move_player(&mut harness.scene, 10.0);
+        harness.tick(50.0);
+        assert_eq!(sent_message_types(&harness.server), vec![1]);
+
+        //Below the threshold doesn't count
+        move_player(&mut harness.scene, 10.1);
+        harness.tick(50.0);
+        assert!(harness.server.take_sent().is_empty());
+
+        //Keepalive
+        for _ in 0..19
+        {
+            harness.tick(50.0);
+        }
+        assert_eq!(sent_message_types(&harness.server), vec![1]);
+    }
+
+    #[test]
+    fn congestion_and_latency_slow_updates_down()
+    {
+        let mut harness = Harness::new();
+        harness.server.accept();
+        harness.tick(16.0);
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 4u32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+        harness.server.take_sent();
+
+        //Congested - nothing is sent and the interval backs off
+        harness.server.set_buffered_amount(100000);
+        harness.tick(50.0);
+        assert!(harness.server.take_sent().is_empty());
+        assert_eq!(harness.server_connection.get_update_interval(), 100.0);
+
+        harness.server.set_buffered_amount(0);
+        harness.tick(100.0);
+        assert_eq!(sent_message_types(&harness.server), vec![1]);
+        assert_eq!(harness.server_connection.get_update_interval(), 75.0);
+
+        //Pinged every couple of seconds, answered with a round trip of 400 ms
+        for _ in 0..19
+        {
+            harness.tick(100.0);
+        }
+        let ping = harness.server.take_sent().into_iter().find(|bytes| i32::from_le_bytes(bytes[0..4].try_into().unwrap()) == 7).unwrap();
+        harness.tick(400.0);
+        harness.server.send_to_client(server_message(8, LOCAL_UUID, &ping[4..8]));
+        harness.tick(16.0);
+
+        assert_eq!(harness.server_connection.get_round_trip_time(), Some(416.0));
+        assert!(harness.server_connection.get_status().contains("416 ms"));
+
+        //Slow connection, so updates stay at half the round trip
+        move_player(&mut harness.scene, 100.0);
+        harness.tick(1000.0);
+        assert_eq!(harness.server_connection.get_update_interval(), 208.0);
+    }
+
+    #[test]
+    fn peers_are_animated_from_their_motion_state()
+    {
+        let mut harness = Harness::new();
+        let mut player = 0;
+        harness.scene.apply_to_entities_with_uid::<PlayerInput, _>(|entity_uid: usize, _player_input: &mut PlayerInput|
+        {
+            player = entity_uid;
+        });
+        harness.scene.add_component::<Animation<Sprite>>(player, Animation::<Sprite>::new(HashMap::new(), AnimationState::FacingLeft, 50.0, glm::vec2(0.0,0.0), 0.0, glm::vec2(1.0,1.0)));
+
+        harness.server.accept();
+        harness.tick(16.0);
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[1u32.to_le_bytes(), 8u32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+        harness.server.take_sent();
+
+        //Our own update carries how we're moving: 4 (type) + 8 (x,y) + 12 (motion state)
+        harness.tick(50.0);
+        let sent = harness.server.take_sent();
+        assert_eq!(sent[0].len(), 24);
+        assert_eq!(sent[0][12 + 9], AnimationState::FacingLeft.id());
+
+        //A peer walking left on the spot - nothing to guess from its position
+        let motion_state = [(-1.0f32).to_le_bytes(), 0.0f32.to_le_bytes()].concat();
+        let payload = [&100.0f32.to_le_bytes()[..], &0.0f32.to_le_bytes(), &motion_state, &[0, AnimationState::WalkingLeft.id()], &MOTION_FLAG_GROUNDED.to_le_bytes()].concat();
+        harness.server.send_to_client(server_message(1, PEER_UUID, &payload));
+        harness.tick(16.0);
+
+        run_peer_interpolation_system(&mut harness.scene, &harness.server_connection);
+        run_ai_system(&mut harness.scene, 16.0);
+
+        let peer = harness.scene.get_remote_peers()[0].1;
+        let mut animation_state : Option<AnimationState> = None;
+        harness.scene.apply_to_entity::<Animation<Sprite>, _>(peer, |animation: &mut Animation<Sprite>|
+        {
+            animation_state = Some(animation.get_animation_state().clone());
+        });
+
+        assert!(animation_state == Some(AnimationState::WalkingLeft));
+    }
+
+    #[test]
+    fn newer_server_protocol_is_refused()
+    {
+        let mut harness = Harness::new();
+
+        harness.server.accept();
+        harness.tick(16.0);
+        harness.server.send_to_client(server_message(5, LOCAL_UUID, &[99u32.to_le_bytes(), 1u32.to_le_bytes()].concat()));
+        harness.tick(16.0);
+
+        assert!(harness.server_connection.get_state() == ConnectionState::Closed);
+        assert!(harness.server.was_closed_by_client());
+        assert!(harness.server_connection.get_status().contains("v99"));
+
+        //Refusal is final, no reconnecting
+        harness.tick(60000.0);
+        assert_eq!(harness.server.get_endpoints().len(), 1);
+    }
+
+    #[test]
+    fn dropped_connection_clears_peers_and_reconnects_with_backoff()
+    {
+        let mut harness = Harness::new();
+        harness.handshake();
+
+        harness.server.send_to_client(update_message(PEER_UUID, 100.0, 0.0));
+        harness.tick(16.0);
+        assert_eq!(harness.scene.get_remote_peers().len(), 1);
+
+        harness.server.drop_connection();
+        harness.tick(16.0);
+
+        assert!(harness.scene.get_remote_peers().is_empty());
+        assert!(!harness.scene.is_local_peer(&peer_id(LOCAL_UUID)));
+        assert!(matches!(harness.server_connection.get_state(), ConnectionState::Backoff { .. }));
+
+        //First retry after a second
+        harness.tick(999.0);
+        assert_eq!(harness.server.get_endpoints().len(), 1);
+        harness.tick(1.0);
+        assert_eq!(harness.server.get_endpoints().len(), 2);
+
+        //Fails again, so the next retry waits twice as long
+        harness.server.drop_connection();
+        harness.tick(16.0);
+        harness.tick(1999.0);
+        assert_eq!(harness.server.get_endpoints().len(), 2);
+        harness.tick(1.0);
+        assert_eq!(harness.server.get_endpoints().len(), 3);
+
+        //Handshakes again once back
+        harness.server.take_sent();
+        harness.handshake();
+        assert!(harness.scene.is_local_peer(&peer_id(LOCAL_UUID)));
+    }
+}
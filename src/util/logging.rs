@@ -1,12 +1,19 @@
 use web_sys::console;
-use wasm_bindgen::JsValue;
 
 pub fn log_value<T: wasm_bindgen::JsCast>(value: &T)
 {
     console::log_1(&value.into());
 }
 
+//Goes to the browser console, or stderr when running natively (e.g. tests)
+#[cfg(target_arch = "wasm32")]
 pub fn log(value : &str)
 {
-    console::log_1(&JsValue::from_str(value).into());
+    console::log_1(&wasm_bindgen::JsValue::from_str(value).into());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn log(value : &str)
+{
+    eprintln!("{}", value);
 }
\ No newline at end of file
@@ -15,6 +15,15 @@ pub fn get_rectangular_texture_coordinates(top_left_pixel_coordinate: &[i32;2],
     return [left_top,left_bottom,right_bottom,right_top];
 }
 
+//Given the centers and full sizes of two axis-aligned rectangles, check whether they overlap
+pub fn rectangles_overlap(a_position: &glm::Vec2, a_size: &glm::Vec2, b_position: &glm::Vec2, b_size: &glm::Vec2) -> bool
+{
+    let x_distance = (a_position.x - b_position.x).abs();
+    let y_distance = (a_position.y - b_position.y).abs();
+
+    x_distance <= (a_size.x + b_size.x) * 0.5 && y_distance <= (a_size.y + b_size.y) * 0.5
+}
+
 /*
 pub fn world_position_to_screen_translation(position: &glm::Vec2, world_size: &glm::Vec2) -> glm::Vec2
 {
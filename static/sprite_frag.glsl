@@ -3,6 +3,7 @@ precision highp float;
 
 in vec2 vertex_texture_coordinates;
 in float vertex_texture_index;
+in vec4 vertex_color;
 
 out vec4 outColor;
 uniform sampler2D u_texture_0;
@@ -11,7 +12,11 @@ uniform sampler2D u_texture_2;
 
 void main() 
 {
-    if( int(vertex_texture_index) == 0)
+    if(vertex_texture_index < 0.0)
+    {
+        //Untextured (e.g. debug lines)
+        outColor = vertex_color;
+    } else if( int(vertex_texture_index) == 0)
     {
         outColor = texture(u_texture_0, vertex_texture_coordinates);
     } else if(int(vertex_texture_index) == 1)
@@ -4,6 +4,7 @@ layout(location = 0) in vec3 position;
 layout(location = 1) in float model_matrix_index;
 layout(location = 2) in vec2 texture_coordinates;
 layout(location = 3) in float texture_index;
+layout(location = 4) in vec4 color;
 
 uniform mat4 vp_matrix;
 
@@ -14,10 +15,12 @@ uniform ModelMatrixBlock
 
 out vec2 vertex_texture_coordinates;
 out float vertex_texture_index;
+out vec4 vertex_color;
 
 void main() 
 {
     gl_Position = vp_matrix * model_matrices.matrices[int(model_matrix_index)] * vec4(position,1.0);
     vertex_texture_coordinates = texture_coordinates;
     vertex_texture_index = texture_index;
+    vertex_color = color;
 }
\ No newline at end of file
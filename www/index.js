@@ -1,8 +1,23 @@
 import {Game} from "possum_world"
 
+const DEFAULT_SERVER = "wss://backend.poss.ly/";
+
+//?server=ws://127.0.0.1:8000 picks another server, ?offline starts without one
+const getServerEndpoint = () =>
+{
+    let params = new URLSearchParams(window.location.search);
+
+    if(params.has("offline"))
+    {
+        return undefined;
+    }
+
+    return params.get("server") || DEFAULT_SERVER;
+};
+
 export function init(textures,shader_sources)
 {
-    const game = Game.new(document);
+    const game = Game.new(document, getServerEndpoint());
 
     let setCanvasSizeFn = (game_object) => 
     {
@@ -21,31 +36,60 @@ export function init(textures,shader_sources)
     });
 
     //Attach event listeners for keypresses
+    //Typing into a page input (e.g. the chat bar) shouldn't also control the game
+    let isTypingInPage = (event) => event.target instanceof HTMLInputElement;
+
     addEventListener("keydown",(event) => 
     {
-        game.process_keypress_event(true,event.code);
+        if(isTypingInPage(event))
+        {
+            return;
+        }
+
+        game.process_keypress_event(true,event.code,event.key);
+
+        //Stop keys like backspace or space from also acting on the page while typing in the game
+        if(game.is_text_entry_active())
+        {
+            event.preventDefault();
+        }
     });
 
     addEventListener("keyup",(event) => 
     {
-        game.process_keypress_event(false,event.code);
+        game.process_keypress_event(false,event.code,event.key);
     });
 
     canvas.addEventListener("pointerdown",(event) =>
     {
-        game.process_click_event(true,event.offsetX,event.offsetY);
+        game.process_click_event(true,event.offsetX,event.offsetY,event.button,event.pointerId);
     });
 
     canvas.addEventListener("pointermove",(event) =>
     {
-        game.process_mouse_move_event(event.offsetX,event.offsetY);
+        game.process_mouse_move_event(event.offsetX,event.offsetY,event.pointerId);
     });
 
     addEventListener("pointerup", (event) => 
     {
-        game.process_click_event(false,event.offsetX,event.offsetY);
+        game.process_click_event(false,event.offsetX,event.offsetY,event.button,event.pointerId);
     });
 
+    addEventListener("pointercancel", (event) =>
+    {
+        game.process_pointer_cancel_event(event.pointerId);
+    });
+
+    canvas.addEventListener("wheel",(event) =>
+    {
+        event.preventDefault();
+
+        //Some browsers scroll by lines rather than pixels
+        let scale = event.deltaMode == WheelEvent.DOM_DELTA_LINE ? 16 : 1;
+        game.process_scroll_event(event.deltaX * scale,event.deltaY * scale);
+    }, { passive: false });
+
+    let connectionStatus = document.querySelector(".connection-status")
     let chatInput = document.querySelector(".chat-bar-input")
     let chatSubmit = document.querySelector(".chat-bar-submit")
 
@@ -96,8 +140,27 @@ export function init(textures,shader_sources)
 
         let delta_time = (now - previous); //in MS
 
+        //Gamepads have no events for button/axis changes, so poll the first connected one every frame
+        let gamepad = navigator.getGamepads ? navigator.getGamepads().find((g) => g) : null;
+
+        if(gamepad)
+        {
+            game.process_gamepad_state(Float32Array.from(gamepad.axes), Float32Array.from(gamepad.buttons, (b) => b.value));
+        } else
+        {
+            game.process_gamepad_state(new Float32Array(), new Float32Array());
+        }
+
         game.run_systems(delta_time);
 
+        let status = game.get_connection_status();
+
+        if(connectionStatus.textContent !== status)
+        {
+            connectionStatus.textContent = status;
+            connectionStatus.dataset.state = game.get_connection_state();
+        }
+
         requestAnimationFrame(gameLoop);
     };
 